@@ -0,0 +1,221 @@
+//! Timing leakage detection
+//!
+//! Statistical detection of timing leaks in the spirit of dudect: a
+//! function is repeatedly run on inputs drawn from two classes, a fixed
+//! class and a random class, its execution time is measured in cycles
+//! and Welch's t-test is applied on the two sets of measurements. A
+//! large `|t|` value is an evidence that the execution time depends on
+//! the inputs.
+use std::intrinsics;
+use std::num::Float;
+use std::rand::random;
+
+use sbuf::{PooledGuardedAllocator, SBuf};
+use utils;
+
+
+/// Default threshold above which `|t|` is considered as an evidence of
+/// a timing leak.
+pub static THRESHOLD: f64 = 10.0;
+
+/// Class of the inputs of a measurement.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Class {
+    /// Fixed input, or input chosen to hit a specific case.
+    Fixed,
+    /// Random input.
+    Random
+}
+
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline(always)]
+fn cycles() -> u64 {
+    let lo: u32;
+    let hi: u32;
+    // rdtsc is not serializing, the fences keep the measured code from
+    // being reordered around the reads of the counter.
+    unsafe {
+        asm!("lfence; rdtsc; lfence"
+             : "={eax}"(lo), "={edx}"(hi) : : "memory" : "volatile");
+    }
+    (hi as u64 << 32) | lo as u64
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn cycles() -> u64 {
+    use libc::types::os::arch::c95::c_int;
+    use libc::types::os::common::posix01::timespec;
+
+    extern {
+        fn clock_gettime(clk_id: c_int, tp: *mut timespec) -> c_int;
+    }
+
+    // CLOCK_MONOTONIC
+    let clock_monotonic: c_int = 1;
+    let mut ts = timespec {
+        tv_sec: 0,
+        tv_nsec: 0
+    };
+    unsafe {
+        clock_gettime(clock_monotonic, &mut ts);
+    }
+    ts.tv_sec as u64 * 1000000000 + ts.tv_nsec as u64
+}
+
+// Prevent the optimizer from discarding a result.
+fn consume(b: bool) {
+    let mut sink = false;
+    unsafe {
+        intrinsics::volatile_store(&mut sink as *mut bool, b);
+    }
+}
+
+
+// Online mean and variance (Welford's algorithm).
+struct Stats {
+    n: f64,
+    mean: f64,
+    m2: f64
+}
+
+impl Stats {
+    fn new() -> Stats {
+        Stats {
+            n: 0.0,
+            mean: 0.0,
+            m2: 0.0
+        }
+    }
+
+    fn push(&mut self, x: f64) {
+        self.n += 1.0;
+        let delta = x - self.mean;
+        self.mean += delta / self.n;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        self.m2 / (self.n - 1.0)
+    }
+}
+
+// Welch's t statistic.
+fn welch_t(a: &Stats, b: &Stats) -> f64 {
+    (a.mean - b.mean) / (a.variance() / a.n + b.variance() / b.n).sqrt()
+}
+
+
+/// Measure timings of `op` and return Welch's t statistic.
+///
+/// Run `samples` measurements, for each one a class is randomly chosen,
+/// `gen` builds an input of that class (this is not measured) then the
+/// execution of `op` on this input is timed. The slowest measurements are
+/// discarded as they are mostly caused by interrupts or context switches.
+pub fn measure<I>(samples: uint, gen: |Class| -> I, op: |&mut I|) -> f64 {
+    assert!(samples >= 100);
+
+    let mut times: Vec<(Class, u64)> = Vec::with_capacity(samples);
+    for _ in range(0u, samples) {
+        let class = if random::<bool>() { Fixed } else { Random };
+        let mut input = gen(class);
+        let start = cycles();
+        op(&mut input);
+        let end = cycles();
+        times.push((class, end - start));
+    }
+
+    let mut sorted: Vec<u64> = times.iter().map(|&(_, t)| t).collect();
+    sorted.sort();
+    let cutoff = sorted[sorted.len() * 9 / 10];
+
+    let mut stats = [Stats::new(), Stats::new()];
+    for &(class, t) in times.iter() {
+        if t <= cutoff {
+            stats[class as uint].push(t as f64);
+        }
+    }
+    welch_t(&stats[0], &stats[1])
+}
+
+/// Return `true` iff `|t|` is lower than `THRESHOLD`.
+pub fn is_constant_time(t: f64) -> bool {
+    t.abs() < THRESHOLD
+}
+
+fn random_bytes(len: uint) -> Vec<u8> {
+    Vec::from_fn(len, |_| random::<u8>())
+}
+
+/// Measure `utils::bytes_eq` comparing a secret with itself (fixed
+/// class) or with random buffers (random class).
+pub fn check_bytes_eq(samples: uint) -> f64 {
+    let secret = random_bytes(512);
+    measure(samples, |class| {
+        match class {
+            Fixed => secret.clone(),
+            Random => random_bytes(512)
+        }
+    }, |input| consume(utils::bytes_eq(secret[], input[])))
+}
+
+/// Measure `utils::bytes_cswap` on random buffers with a condition
+/// always set to `0` (fixed class) or random (random class).
+pub fn check_bytes_cswap(samples: uint) -> f64 {
+    measure(samples, |class| {
+        let cond = match class {
+            Fixed => 0i64,
+            Random => random::<bool>() as i64
+        };
+        (cond,
+         Vec::from_fn(64, |_| random::<i64>()),
+         Vec::from_fn(64, |_| random::<i64>()))
+    }, |input| {
+        let (cond, ref mut x, ref mut y) = *input;
+        utils::bytes_cswap(cond, x[mut], y[mut])
+    })
+}
+
+/// Measure `PartialEq` of `SBuf` comparing a secret buffer with a copy
+/// of itself (fixed class) or with random buffers (random class).
+pub fn check_sbuf_eq(samples: uint) -> f64 {
+    let secret: SBuf<PooledGuardedAllocator, u8> = SBuf::new_rand(512);
+    measure(samples, |class| {
+        match class {
+            Fixed => secret.clone(),
+            Random => SBuf::new_rand(512)
+        }
+    }, |input| consume(secret == *input))
+}
+
+
+#[cfg(test)]
+mod test {
+    use ct_test;
+
+
+    // Timing measurements are disturbed by concurrently running tests,
+    // run them alone with `RUST_TEST_TASKS=1` and `--ignored`.
+    static SAMPLES: uint = 20000;
+
+    #[test]
+    #[ignore]
+    fn test_bytes_eq() {
+        let t = ct_test::check_bytes_eq(SAMPLES);
+        assert!(ct_test::is_constant_time(t), "t = {}", t);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_bytes_cswap() {
+        let t = ct_test::check_bytes_cswap(SAMPLES);
+        assert!(ct_test::is_constant_time(t), "t = {}", t);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_sbuf_eq() {
+        let t = ct_test::check_sbuf_eq(SAMPLES);
+        assert!(ct_test::is_constant_time(t), "t = {}", t);
+    }
+}
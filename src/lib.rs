@@ -13,6 +13,7 @@
 #![feature(default_type_params)]
 #![feature(slicing_syntax)]
 #![feature(phase)]
+#![feature(asm)]
 
 #[cfg(test)] extern crate test;
 #[cfg(test)] #[phase(plugin, link)] extern crate log;
@@ -24,3 +25,7 @@ extern crate serialize;
 pub mod macros;
 pub mod utils;
 pub mod sbuf;
+pub mod svec;
+pub mod sstring;
+pub mod pem;
+pub mod ct_test;
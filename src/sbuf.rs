@@ -1,14 +1,20 @@
 //! Secure Buffer
 use alloc::heap;
 use libc::consts::os::posix88::{MAP_ANON, MAP_PRIVATE, MAP_FAILED,
-                                PROT_READ, PROT_WRITE, PROT_NONE};
-use libc::funcs::posix88::mman;
+                                PROT_READ, PROT_WRITE, PROT_NONE,
+                                O_RDONLY, S_IFMT, S_IFREG, EINTR};
+use libc::funcs::posix88::{fcntl, mman, stat_, unistd};
 use libc::types::common::c95::c_void;
-use libc::types::os::arch::c95::size_t;
+use libc::types::os::arch::c95::{c_int, size_t};
+use libc::types::os::arch::posix01::stat;
 use serialize::{Encodable, Encoder, Decodable, Decoder};
 use serialize::hex::ToHex;
+use std::c_str::ToCStr;
+use std::cell::Cell;
+use std::cmp;
 use std::fmt;
 use std::intrinsics;
+use std::io::{mod, IoError, IoResult, Reader, Writer};
 use std::iter::AdditiveIterator;
 use std::mem;
 use std::ops;
@@ -16,18 +22,127 @@ use std::os;
 use std::ptr;
 use std::rand::Rng;
 use std::raw::Slice;
+use std::rt::mutex::{StaticNativeMutex, NATIVE_MUTEX_INIT};
 use std::slice::{Items, MutItems};
+use std::string;
 
 use utils;
 
 
+/// Errors returned by fallible allocations of secure buffers. System
+/// call failures carry the value of `errno`.
+#[deriving(Clone, PartialEq, Eq)]
+pub enum SBufError {
+    /// `mmap` failed.
+    Mmap(int),
+    /// `mprotect` failed.
+    Mprotect(int),
+    /// `mlock` failed, typically when `RLIMIT_MEMLOCK` is reached.
+    Mlock(int),
+    /// `madvise` failed.
+    Madvise(int),
+    /// `minherit` failed.
+    Minherit(int),
+    /// `munlock` failed.
+    Munlock(int),
+    /// `munmap` failed.
+    Munmap(int),
+    /// Size in bytes of the requested buffer overflows `uint`.
+    LengthOverflow,
+    /// The system RNG failed.
+    Random(IoError),
+    /// Operation not supported by the allocator.
+    Unsupported
+}
+
+impl SBufError {
+    /// Return the `errno` value associated to this error if any.
+    pub fn errno(&self) -> Option<int> {
+        match *self {
+            Mmap(errno) | Mprotect(errno) | Mlock(errno) | Madvise(errno) |
+            Minherit(errno) | Munlock(errno) | Munmap(errno) => Some(errno),
+            LengthOverflow | Random(_) | Unsupported => None
+        }
+    }
+}
+
+impl fmt::Show for SBufError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Mmap(_) => "mmap",
+            Mprotect(_) => "mprotect",
+            Mlock(_) => "mlock",
+            Madvise(_) => "madvise",
+            Minherit(_) => "minherit",
+            Munlock(_) => "munlock",
+            Munmap(_) => "munmap",
+            LengthOverflow => return write!(f, "alloc length overflow"),
+            Random(ref err) => return write!(f, "random generation failed: {}",
+                                             err),
+            Unsupported => return write!(f, "operation not supported by allocator")
+        };
+        let errno = self.errno().unwrap();
+        write!(f, "{} failed: {} ({})", name,
+               os::error_string(errno as uint), errno)
+    }
+}
+
+
+/// Access protection of the memory pages of a buffer.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Protection {
+    /// Memory can be read and written, default protection.
+    ReadWrite,
+    /// Memory can only be read.
+    ReadOnly,
+    /// Memory can't be accessed.
+    NoAccess
+}
+
+fn prot_flags(prot: Protection) -> c_int {
+    match prot {
+        ReadWrite => PROT_READ | PROT_WRITE,
+        ReadOnly => PROT_READ,
+        NoAccess => PROT_NONE
+    }
+}
+
+
 /// Trait for allocators.
 pub trait Allocator {
     fn new() -> Self;
 
+    /// Return allocator's name, used when formatting buffers.
+    fn name(&self) -> &'static str {
+        "Allocator"
+    }
+
     unsafe fn allocate(&self, size: uint, align: uint) -> *mut u8;
 
+    /// Like `allocate` but return an error instead of failing. The
+    /// default implementation simply forwards to `allocate`.
+    unsafe fn try_allocate(&self, size: uint,
+                           align: uint) -> Result<*mut u8, SBufError> {
+        Ok(self.allocate(size, align))
+    }
+
     unsafe fn deallocate(&self, ptr: *mut u8, size: uint, align: uint);
+
+    /// Return `true` if memory returned by this allocator is already
+    /// locked and advised, in which case `mlock`, `madvise` and `munlock`
+    /// are not applied on each individual buffer.
+    fn is_locked(&self) -> bool {
+        false
+    }
+
+    /// Change to `prot` the protection of the pages holding the buffer
+    /// `ptr` of `size` bytes. Only supported by allocators placing each
+    /// buffer on its own pages, the default implementation returns
+    /// `Unsupported`.
+    unsafe fn protect(&self, _ptr: *mut u8, _size: uint, _align: uint,
+                      _prot: Protection) -> Result<(), SBufError> {
+        Err(Unsupported)
+    }
 }
 
 /// Default allocator used to allocate and deallocate memory for secure
@@ -44,6 +159,10 @@ impl Allocator for StdHeapAllocator {
         StdHeapAllocator
     }
 
+    fn name(&self) -> &'static str {
+        "StdHeapAllocator"
+    }
+
     unsafe fn allocate(&self, size: uint, align: uint) -> *mut u8 {
         heap::allocate(size, align)
     }
@@ -74,6 +193,47 @@ mod impmap {
 }
 
 
+// O_CLOEXEC isn't defined by libc. Secret files are opened with it so
+// that their descriptor isn't inherited by a process exec'ed from
+// another thread.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod impcloexec {
+    use libc::types::os::arch::c95::c_int;
+
+    pub static O_CLOEXEC: c_int = 0o2000000;
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod impcloexec {
+    use libc::types::os::arch::c95::c_int;
+
+    pub static O_CLOEXEC: c_int = 0x1000000;
+}
+
+#[cfg(target_os = "freebsd")]
+mod impcloexec {
+    use libc::types::os::arch::c95::c_int;
+
+    pub static O_CLOEXEC: c_int = 0x100000;
+}
+
+#[cfg(target_os = "dragonfly")]
+mod impcloexec {
+    use libc::types::os::arch::c95::c_int;
+
+    pub static O_CLOEXEC: c_int = 0x20000;
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "macos", target_os = "ios",
+              target_os = "freebsd", target_os = "dragonfly")))]
+mod impcloexec {
+    use libc::types::os::arch::c95::c_int;
+
+    pub static O_CLOEXEC: c_int = 0;
+}
+
+
 fn round_up(size: uint, page_size: uint) -> uint {
     assert!(size > 0 && page_size > 0);
     if size % page_size == 0 {
@@ -84,11 +244,91 @@ fn round_up(size: uint, page_size: uint) -> uint {
 }
 
 
+static CANARY_SIZE: uint = 16;
+static CANARY_LOCK: StaticNativeMutex = NATIVE_MUTEX_INIT;
+static mut CANARY_READY: bool = false;
+static mut CANARY: [u8, ..CANARY_SIZE] = [0, ..CANARY_SIZE];
+
+// Return the random canary placed before each guarded buffer, it is
+// generated from `SysRng` on first use, once per process.
+fn canary() -> Result<&'static [u8], SBufError> {
+    unsafe {
+        let _guard = CANARY_LOCK.lock();
+        if !CANARY_READY {
+            match utils::SysRng::new().try_fill_bytes(CANARY[mut]) {
+                Ok(()) => CANARY_READY = true,
+                Err(err) => return Err(Random(err))
+            }
+        }
+        Ok(CANARY[])
+    }
+}
+
+
+// Return the size of the data pages of a guarded buffer of `size` bytes
+// and its canary.
+fn guarded_data_size(size: uint, page_size: uint) -> Result<uint, SBufError> {
+    match size.checked_add(&(CANARY_SIZE + page_size - 1)) {
+        Some(n) => Ok(n - n % page_size),
+        None => Err(LengthOverflow)
+    }
+}
+
+// Map `data_size` bytes of locked memory, a multiple of the page size,
+// between two guard pages. Return the address of the first data page.
+unsafe fn map_guarded(data_size: uint) -> Result<*mut u8, SBufError> {
+    let page_size = os::page_size();
+    let full_size = match data_size.checked_add(&(2 * page_size)) {
+        Some(full_size) => full_size,
+        None => return Err(LengthOverflow)
+    };
+
+    let null_addr: *const u8 = ptr::null();
+    let ptr = mman::mmap(null_addr as *mut c_void,
+                         full_size as size_t,
+                         PROT_READ | PROT_WRITE,
+                         MAP_ANON | MAP_PRIVATE |
+                         impmap::additional_map_flags(),
+                         -1,
+                         0);
+    if ptr == MAP_FAILED {
+        return Err(Mmap(os::errno()));
+    }
+
+    let before_page = ptr;
+    let after_page = intrinsics::offset(ptr as *const c_void,
+                                        (full_size - page_size) as int);
+    if mman::mprotect(before_page, page_size as size_t,
+                      PROT_NONE) != 0 ||
+       mman::mprotect(after_page as *mut c_void, page_size as size_t,
+                      PROT_NONE) != 0 {
+        let err = Mprotect(os::errno());
+        mman::munmap(ptr, full_size as size_t);
+        return Err(err);
+    }
+
+    let data = intrinsics::offset(ptr as *const c_void,
+                                  page_size as int) as *mut u8;
+    match lock_memory(data, data_size) {
+        Ok(()) => (),
+        Err(err) => {
+            mman::munmap(ptr, full_size as size_t);
+            return Err(err);
+        }
+    }
+    Ok(data)
+}
+
+
 /// Guarded heap allocator, add a guarded page before and after
-/// each allocated buffer.
+/// each allocated buffer. The buffer is right-aligned against the
+/// trailing guarded page so that overflows are immediately caught, and
+/// a random canary is placed just before it and checked on deallocation
+/// to detect underflows, the process is aborted if it was corrupted.
+/// Memory pages are locked and advised by the allocator itself.
 // It's very slow and not very space-efficient especially for small
-// buffers. Using a pool of buffers guarded by two pages would be
-// better for performances.
+// buffers. See PooledGuardedAllocator for a better alternative for
+// small buffers.
 pub struct GuardedHeapAllocator;
 
 impl Allocator for GuardedHeapAllocator {
@@ -96,59 +336,271 @@ impl Allocator for GuardedHeapAllocator {
         GuardedHeapAllocator
     }
 
-    unsafe fn allocate(&self, size: uint, _: uint) -> *mut u8 {
-        let page_size = os::page_size();
-        let full_size = round_up(size, page_size) + 2 * page_size;
-
-        let null_addr: *const u8 = ptr::null();
-        let ptr = mman::mmap(null_addr as *mut c_void,
-                             full_size as size_t,
-                             PROT_READ | PROT_WRITE,
-                             MAP_ANON | MAP_PRIVATE |
-                             impmap::additional_map_flags(),
-                             -1,
-                             0);
-        if ptr == MAP_FAILED {
-            let errno = os::errno();
-            panic!("mmap failed: {} ({})",
-                  os::error_string(errno as uint), errno);
-        }
+    fn name(&self) -> &'static str {
+        "GuardedHeapAllocator"
+    }
 
-        let before_page = ptr;
-        let mut ret = mman::mprotect(before_page, page_size as size_t,
-                                     PROT_NONE);
-        if ret != 0 {
-            let errno = os::errno();
-            panic!("mprotect failed: {} ({})",
-                  os::error_string(errno as uint), errno);
+    unsafe fn allocate(&self, size: uint, align: uint) -> *mut u8 {
+        match self.try_allocate(size, align) {
+            Ok(ptr) => ptr,
+            Err(err) => panic!("{}", err)
         }
+    }
 
-        let after_page = intrinsics::offset(ptr as *const c_void,
-                                            (full_size - page_size) as int);
-        ret = mman::mprotect(after_page as *mut c_void, page_size as size_t,
-                             PROT_NONE);
-        if ret != 0 {
-            let errno = os::errno();
-            panic!("mprotect failed: {} ({})",
-                  os::error_string(errno as uint), errno);
-        }
+    unsafe fn try_allocate(&self, size: uint,
+                           align: uint) -> Result<*mut u8, SBufError> {
+        let canary = try!(canary());
+        let data_size = try!(guarded_data_size(size, os::page_size()));
+        let data = try!(map_guarded(data_size));
 
-        intrinsics::offset(ptr as *const c_void, page_size as int) as *mut u8
+        // Right-align the buffer and write the canary just before it.
+        let after_page = data as uint + data_size;
+        let buf = (after_page - size) & !(align - 1);
+        ptr::copy_nonoverlapping_memory((buf - CANARY_SIZE) as *mut u8,
+                                        canary.as_ptr(), CANARY_SIZE);
+        Ok(buf as *mut u8)
     }
 
     unsafe fn deallocate(&self, ptr: *mut u8, size: uint, _: uint) {
         let page_size = os::page_size();
-        let full_size = round_up(size, page_size) + 2 * page_size;
+        let data_size = guarded_data_size(size, page_size)
+                            .ok().expect("alloc length overflow");
+        let full_size = data_size + 2 * page_size;
+
+        let canary_ptr = (ptr as uint - CANARY_SIZE) as *const u8;
+        let buf_canary: &[u8] = mem::transmute(Slice {
+            data: canary_ptr,
+            len: CANARY_SIZE
+        });
+        // The canary was generated when the buffer was allocated.
+        let canary = match canary() {
+            Ok(canary) => canary,
+            Err(_) => intrinsics::abort()
+        };
+        if !utils::bytes_eq(buf_canary, canary) {
+            // Underflow detected, memory is corrupted.
+            intrinsics::abort();
+        }
 
-        let start_page = intrinsics::offset(ptr as *const c_void,
-                                            -(page_size as int));
+        let data = round_up(ptr as uint + size, page_size) - data_size;
+        unlock_memory(data as *mut u8, data_size);
 
+        let start_page = data - page_size;
         let ret = mman::munmap(start_page as *mut c_void,
                                full_size as size_t);
         if ret != 0 {
-            let errno = os::errno();
-            panic!("munmap failed: {} ({})",
-                  os::error_string(errno as uint), errno);
+            panic!("{}", Munmap(os::errno()));
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        true
+    }
+
+    unsafe fn protect(&self, ptr: *mut u8, size: uint, _: uint,
+                      prot: Protection) -> Result<(), SBufError> {
+        let page_size = os::page_size();
+        let start = ptr as uint & !(page_size - 1);
+        let end = round_up(ptr as uint + size, page_size);
+
+        let ret = mman::mprotect(start as *mut c_void,
+                                 (end - start) as size_t,
+                                 prot_flags(prot));
+        if ret != 0 {
+            return Err(Mprotect(os::errno()));
+        }
+        Ok(())
+    }
+}
+
+
+/// Size classes of the slots served by `PooledGuardedAllocator`.
+static POOL_CLASSES: [uint, ..7] = [16, 32, 64, 128, 256, 512, 1024];
+
+/// Number of pages of each arena mapped by `PooledGuardedAllocator`.
+static POOL_ARENA_PAGES: uint = 16;
+
+static POOL_LOCK: StaticNativeMutex = NATIVE_MUTEX_INIT;
+static mut POOL: *mut Pool = 0 as *mut Pool;
+
+struct Arena {
+    base: *mut u8,
+    used: uint
+}
+
+// A slab is a page of an arena split in slots of the same size class.
+struct Slab {
+    page: *mut u8,
+    class: uint,
+    free: Vec<uint>
+}
+
+struct Pool {
+    page_size: uint,
+    arenas: Vec<Arena>,
+    free_pages: Vec<*mut u8>,
+    slabs: Vec<Slab>
+}
+
+impl Pool {
+    fn new() -> Pool {
+        Pool {
+            page_size: os::page_size(),
+            arenas: Vec::new(),
+            free_pages: Vec::new(),
+            slabs: Vec::new()
+        }
+    }
+
+    fn arena_size(&self) -> uint {
+        POOL_ARENA_PAGES * self.page_size
+    }
+
+    // Return a page from a previously released slab or from an arena,
+    // map a new arena if all the existing ones are full.
+    unsafe fn new_page(&mut self) -> Result<*mut u8, SBufError> {
+        match self.free_pages.pop() {
+            Some(page) => return Ok(page),
+            None => ()
+        }
+
+        let full = match self.arenas.last() {
+            Some(arena) => arena.used == POOL_ARENA_PAGES,
+            None => true
+        };
+        if full {
+            // Arenas are mapped directly, they need neither a canary nor
+            // an extra locked page to hold it.
+            let base = try!(map_guarded(self.arena_size()));
+            self.arenas.push(Arena {
+                base: base,
+                used: 0
+            });
+        }
+
+        let page_size = self.page_size;
+        let arena = self.arenas.last_mut().unwrap();
+        let page = arena.base.offset((arena.used * page_size) as int);
+        arena.used += 1;
+        Ok(page)
+    }
+
+    unsafe fn allocate(&mut self, class: uint) -> Result<*mut u8, SBufError> {
+        for slab in self.slabs.iter_mut() {
+            if slab.class == class {
+                match slab.free.pop() {
+                    Some(idx) => {
+                        return Ok(slab.page.offset((idx * class) as int));
+                    }
+                    None => ()
+                }
+            }
+        }
+
+        let page = try!(self.new_page());
+        let count = self.page_size / class;
+        let mut free: Vec<uint> = range(0u, count).rev().collect();
+        let idx = free.pop().unwrap();
+        self.slabs.push(Slab {
+            page: page,
+            class: class,
+            free: free
+        });
+        Ok(page.offset((idx * class) as int))
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8) {
+        let page = (ptr as uint & !(self.page_size - 1)) as *mut u8;
+        let pos = self.slabs.iter().position(|slab| slab.page == page)
+                      .expect("pointer not allocated from pool");
+
+        let count = self.page_size / self.slabs[pos].class;
+        {
+            let slab = &mut self.slabs[mut][pos];
+            let offset = ptr as uint - page as uint;
+            assert!(offset % slab.class == 0);
+            let idx = offset / slab.class;
+            assert!(!slab.free.contains(&idx), "double free of pool slot");
+
+            utils::zero_raw_memory(ptr, slab.class);
+            slab.free.push(idx);
+        }
+
+        // Give the page back once all its slots are released.
+        if self.slabs[pos].free.len() == count {
+            self.slabs.swap_remove(pos);
+            self.free_pages.push(page);
+        }
+    }
+}
+
+// Run `f` on the process-wide pool, the pool is created on first use
+// and its arenas are never unmapped.
+unsafe fn with_pool<R>(f: |&mut Pool| -> R) -> R {
+    let _guard = POOL_LOCK.lock();
+    if POOL.is_null() {
+        POOL = mem::transmute(box Pool::new());
+    }
+    f(&mut *POOL)
+}
+
+// Return the size class used for an allocation of `size` bytes aligned
+// on `align`, or `None` if it is too large to be pooled.
+fn pool_class(size: uint, align: uint) -> Option<uint> {
+    let size = cmp::max(size, align);
+    POOL_CLASSES.iter().find(|&&class| class >= size).map(|&class| class)
+}
+
+
+/// Pooled guarded heap allocator, small buffers are carved out of
+/// arenas of pages, each arena is mapped between two guarded pages,
+/// locked and advised once. Within an arena each page is split in
+/// slots of a same size class, slots are zeroed-out when they are
+/// released. Buffers larger than the largest size class are directly
+/// allocated with `GuardedHeapAllocator`.
+pub struct PooledGuardedAllocator;
+
+impl Allocator for PooledGuardedAllocator {
+    fn new() -> PooledGuardedAllocator {
+        PooledGuardedAllocator
+    }
+
+    fn name(&self) -> &'static str {
+        "PooledGuardedAllocator"
+    }
+
+    unsafe fn allocate(&self, size: uint, align: uint) -> *mut u8 {
+        match self.try_allocate(size, align) {
+            Ok(ptr) => ptr,
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    unsafe fn try_allocate(&self, size: uint,
+                           align: uint) -> Result<*mut u8, SBufError> {
+        match pool_class(size, align) {
+            Some(class) => with_pool(|pool| pool.allocate(class)),
+            None => GuardedHeapAllocator.try_allocate(size, align)
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, size: uint, align: uint) {
+        match pool_class(size, align) {
+            Some(_) => with_pool(|pool| pool.deallocate(ptr)),
+            None => GuardedHeapAllocator.deallocate(ptr, size, align)
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        true
+    }
+
+    unsafe fn protect(&self, ptr: *mut u8, size: uint, align: uint,
+                      prot: Protection) -> Result<(), SBufError> {
+        // Pooled slots share their pages with other buffers.
+        match pool_class(size, align) {
+            Some(_) => Err(Unsupported),
+            None => GuardedHeapAllocator.protect(ptr, size, align, prot)
         }
     }
 }
@@ -163,8 +615,10 @@ mod impadv {
     use libc::types::os::arch::c95::{c_int, size_t};
     use std::os;
 
+    use super::{SBufError, Madvise};
+
 
-    pub unsafe fn madvise(ptr: *mut u8, size: uint) {
+    pub unsafe fn madvise(ptr: *mut u8, size: uint) -> Result<(), SBufError> {
         let dont_dump: c_int = 16;
         let ret = bsd44::madvise(ptr as *mut c_void, size as size_t,
                                  dont_dump | MADV_DONTFORK);
@@ -176,10 +630,10 @@ mod impadv {
             // There should be a better way to check for the availability
             // of this flag in the kernel and in the libc.
             if errno != EINVAL as int {
-                panic!("madvise failed: {} ({})",
-                      os::error_string(errno as uint), errno);
+                return Err(Madvise(errno));
             }
         }
+        Ok(())
     }
 }
 
@@ -191,22 +645,27 @@ mod impadv {
     use libc::types::os::arch::c95::size_t;
     use std::os;
 
+    use super::{SBufError, Madvise};
 
-    pub unsafe fn madvise(ptr: *mut u8, size: uint) {
+
+    pub unsafe fn madvise(ptr: *mut u8, size: uint) -> Result<(), SBufError> {
         let ret = bsd44::madvise(ptr as *mut c_void, size as size_t,
                                  MADV_ZERO_WIRED_PAGES);
         if ret != 0 {
-            let errno = os::errno();
-            panic!("madvise failed: {} ({})",
-                  os::error_string(errno as uint), errno);
+            return Err(Madvise(os::errno()));
         }
+        Ok(())
     }
 }
 
 #[cfg(not(any(target_os = "linux", target_os = "android",
               target_os = "macos", target_os = "ios")))]
 mod impadv {
-    pub unsafe fn madvise(_: *mut u8, _: uint) {
+    use super::SBufError;
+
+
+    pub unsafe fn madvise(_: *mut u8, _: uint) -> Result<(), SBufError> {
+        Ok(())
     }
 }
 
@@ -217,6 +676,8 @@ mod impinh {
     pub use libc::types::os::arch::c95::{c_int, size_t};
     use std::os;
 
+    use super::{SBufError, Minherit};
+
 
     mod bsdext {
         extern {
@@ -225,53 +686,85 @@ mod impinh {
         }
     }
 
-    pub unsafe fn minherit(ptr: *mut u8, size: uint) {
+    pub unsafe fn minherit(ptr: *mut u8,
+                           size: uint) -> Result<(), SBufError> {
         // Value named INHERIT_NONE on freebsd and VM_INHERIT_NONE on
         // macos/ios.
         let inherit_none: c_int = 2;
         let ret = bsdext::minherit(ptr as *mut c_void, size as size_t,
                                    inherit_none);
         if ret != 0 {
-            let errno = os::errno();
-            panic!("minherit failed: {} ({})",
-                  os::error_string(errno as uint), errno);
+            return Err(Minherit(os::errno()));
         }
+        Ok(())
     }
 }
 
 #[cfg(not(any(target_os = "macos", target_os = "ios",
               target_os = "freebsd")))]
 mod impinh {
-    pub unsafe fn minherit(_: *mut u8, _: uint) {
+    use super::SBufError;
+
+
+    pub unsafe fn minherit(_: *mut u8, _: uint) -> Result<(), SBufError> {
+        Ok(())
+    }
+}
+
+
+// mlock, madvise and minherit memory region.
+unsafe fn lock_memory(ptr: *mut u8, size: uint) -> Result<(), SBufError> {
+    // mlock
+    let ret = mman::mlock(ptr as *const c_void, size as size_t);
+    if ret != 0 {
+        return Err(Mlock(os::errno()));
+    }
+
+    // madvise and minherit
+    let ret = self::impadv::madvise(ptr, size).and_then(|_| {
+        self::impinh::minherit(ptr, size)
+    });
+    if ret.is_err() {
+        mman::munlock(ptr as *const c_void, size as size_t);
     }
+    ret
 }
 
+// munlock memory region.
+unsafe fn unlock_memory(ptr: *mut u8, size: uint) {
+    let ret = mman::munlock(ptr as *const c_void, size as size_t);
+    if ret != 0 {
+        panic!("{}", Munlock(os::errno()));
+    }
+}
 
-unsafe fn alloc<A: Allocator, T>(count: uint) -> *mut T {
+unsafe fn try_alloc<A: Allocator, T>(count: uint) -> Result<*mut T, SBufError> {
     let size_of_t = mem::size_of::<T>();
+    let align = mem::min_align_of::<T>();
 
     assert!(size_of_t != 0 && count != 0);
 
-    let size = count.checked_mul(&size_of_t)
-                    .expect("alloc length overflow");
+    let size = match count.checked_mul(&size_of_t) {
+        Some(size) => size,
+        None => return Err(LengthOverflow)
+    };
 
     // allocate
     let allocator: A = Allocator::new();
-    let ptr = allocator.allocate(size, mem::min_align_of::<T>()) as *mut T;
-
-    // mlock
-    let ret = mman::mlock(ptr as *const c_void, size as size_t);
-    if ret != 0 {
-        let errno = os::errno();
-        panic!("mlock failed: {} ({})",
-              os::error_string(errno as uint), errno);
+    let ptr = try!(allocator.try_allocate(size, align));
+
+    // mlock, madvise and minherit
+    if !allocator.is_locked() {
+        match lock_memory(ptr, size) {
+            Ok(()) => (),
+            Err(err) => {
+                allocator.deallocate(ptr, size, align);
+                return Err(err);
+            }
+        }
     }
 
-    // madvise and minherit
-    self::impadv::madvise(ptr as *mut u8, size);
-    self::impinh::minherit(ptr as *mut u8, size);
-
-    ptr
+    Ok(ptr as *mut T)
 }
 
 unsafe fn dealloc<A: Allocator, T>(ptr: *mut T, count: uint) {
@@ -285,35 +778,48 @@ unsafe fn dealloc<A: Allocator, T>(ptr: *mut T, count: uint) {
                     .expect("alloc length overflow");
 
     // zero-out
-    // FIXME: not sure how much this llvm intrinsics could not be
-    // optimized-out, maybe it would be better to use memset_s.
-    intrinsics::volatile_set_memory(ptr, 0, count);
+    utils::zero_raw_memory(ptr, count);
 
     // munlock
-    let ret = mman::munlock(ptr as *const c_void, size as size_t);
-    if ret != 0 {
-        let errno = os::errno();
-        panic!("munlock failed: {} ({})",
-              os::error_string(errno as uint), errno);
+    let allocator: A = Allocator::new();
+    if !allocator.is_locked() {
+        unlock_memory(ptr as *mut u8, size);
     }
 
     // deallocate
-    let allocator: A = Allocator::new();
     allocator.deallocate(ptr as *mut u8, size, mem::min_align_of::<T>())
 }
 
 
+fn or_panic<A, T>(res: Result<SBuf<A, T>, SBufError>) -> SBuf<A, T> {
+    match res {
+        Ok(n) => n,
+        Err(err) => panic!("{}", err)
+    }
+}
+
+
 /// Secure Buffer.
+///
+/// Once sealed with `seal_readonly` or `seal_noaccess` its content must
+/// be accessed through `with_read` and `with_write`, the accessors
+/// returning slices or references fail if the sealed protection doesn't
+/// allow the access.
 pub struct SBuf<A, T> {
     len: uint,
-    ptr: *mut T
+    ptr: *mut T,
+    prot: Protection,
+    // Number of nested scoped accesses in progress.
+    access: Cell<uint>
 }
 
 impl<A: Allocator, T> SBuf<A, T> {
     fn from_raw_parts(length: uint, ptr: *mut T) -> SBuf<A, T> {
         SBuf {
             len: length,
-            ptr: ptr
+            ptr: ptr,
+            prot: ReadWrite,
+            access: Cell::new(0)
         }
     }
 
@@ -321,55 +827,98 @@ impl<A: Allocator, T> SBuf<A, T> {
         self.len * mem::size_of::<T>()
     }
 
-    fn with_length(length: uint) -> SBuf<A, T> {
+    fn try_with_length(length: uint) -> Result<SBuf<A, T>, SBufError> {
         if mem::size_of::<T>() == 0 || length == 0 {
-            return SBuf::from_raw_parts(0, 0 as *mut T);
+            return Ok(SBuf::from_raw_parts(0, 0 as *mut T));
         }
 
-        let ptr = unsafe {
-            alloc::<A, T>(length)
-        };
-        SBuf::from_raw_parts(length, ptr)
+        let ptr = try!(unsafe {
+            try_alloc::<A, T>(length)
+        });
+        Ok(SBuf::from_raw_parts(length, ptr))
+    }
+
+    fn with_length(length: uint) -> SBuf<A, T> {
+        or_panic(SBuf::try_with_length(length))
     }
 
     /// New allocated buffer with its memory zeroed-out.
-    #[allow(experimental)]
     pub fn new_zero(length: uint) -> SBuf<A, T> {
-        let n = SBuf::with_length(length);
+        or_panic(SBuf::try_new_zero(length))
+    }
+
+    /// Like `new_zero` but return an error if the allocation failed.
+    #[allow(experimental)]
+    pub fn try_new_zero(length: uint) -> Result<SBuf<A, T>, SBufError> {
+        let n = try!(SBuf::try_with_length(length));
         unsafe {
             ptr::zero_memory(n.ptr, length);
         }
-        n
+        Ok(n)
     }
 
     /// New allocated buffer with its memory randomly generated.
     pub fn new_rand(length: uint) -> SBuf<A, T> {
-        let mut n = SBuf::with_length(length);
-        let rng = &mut utils::urandom_rng();
-        rng.fill_bytes(unsafe {
-            mem::transmute(Slice {
-                data: n.as_mut_ptr() as *const u8,
-                len: n.size()
-            })
-        });
-        n
+        or_panic(SBuf::try_new_rand(length))
+    }
+
+    /// Like `new_rand` but return an error if the allocation or the
+    /// system RNG failed. Memory is generated by `utils::SysRng`.
+    pub fn try_new_rand(length: uint) -> Result<SBuf<A, T>, SBufError> {
+        let mut n = try!(SBuf::try_with_length(length));
+        match utils::SysRng::new().try_fill_bytes(unsafe { n.raw_bytes() }) {
+            Ok(()) => (),
+            Err(err) => return Err(Random(err))
+        }
+        utils::classify(n[]);
+        Ok(n)
+    }
+
+    /// New allocated buffer with its memory generated from `rng`. Use a
+    /// seeded RNG like `utils::ChaChaRng` to get reproducible buffers.
+    pub fn new_rand_with<R: Rng>(length: uint, rng: &mut R) -> SBuf<A, T> {
+        or_panic(SBuf::try_new_rand_with(length, rng))
+    }
+
+    /// Like `new_rand_with` but return an error if the allocation failed.
+    pub fn try_new_rand_with<R: Rng>(length: uint,
+                                     rng: &mut R) -> Result<SBuf<A, T>,
+                                                            SBufError> {
+        let mut n = try!(SBuf::try_with_length(length));
+        rng.fill_bytes(unsafe { n.raw_bytes() });
+        utils::classify(n[]);
+        Ok(n)
     }
 
     /// New allocated buffer with its `length` elements initilized from
     /// provided closure `op`.
     pub fn from_fn(length: uint, op: |uint| -> T) -> SBuf<A, T> {
-        let mut n = SBuf::with_length(length);
+        or_panic(SBuf::try_from_fn(length, op))
+    }
+
+    /// Like `from_fn` but return an error if the allocation failed.
+    pub fn try_from_fn(length: uint,
+                       op: |uint| -> T) -> Result<SBuf<A, T>, SBufError> {
+        let mut n = try!(SBuf::try_with_length(length));
         for i in range(0u, length) {
             n[i] = op(i);
         }
-        n
+        Ok(n)
     }
 
     /// New buffer from slice.
     pub fn from_slice(values: &[T]) -> SBuf<A, T> {
+        or_panic(SBuf::try_from_slice(values))
+    }
+
+    /// Like `from_slice` but return an error if the allocation failed.
+    pub fn try_from_slice(values: &[T]) -> Result<SBuf<A, T>, SBufError> {
+        let n = try!(SBuf::try_with_length(values.len()));
         unsafe {
-            SBuf::from_buf(values.as_ptr(), values.len())
+            ptr::copy_nonoverlapping_memory(n.ptr, values.as_ptr(), n.len);
         }
+        utils::classify(n[]);
+        Ok(n)
     }
 
     /// New buffer from unsafe buffer.
@@ -426,28 +975,62 @@ impl<A: Allocator, T> SBuf<A, T> {
         }
     }
 
-    /// Work with `self` as a slice.
+    unsafe fn raw_slice(&self) -> &[T] {
+        mem::transmute(Slice {
+            data: self.as_ptr(),
+            len: self.len
+        })
+    }
+
+    unsafe fn raw_mut_slice(&mut self) -> &mut [T] {
+        mem::transmute(Slice {
+            data: self.as_mut_ptr() as *const T,
+            len: self.len
+        })
+    }
+
+    unsafe fn raw_bytes(&mut self) -> &mut [u8] {
+        mem::transmute(Slice {
+            data: self.as_mut_ptr() as *const u8,
+            len: self.size()
+        })
+    }
+
+    // Fail if buffer's memory is sealed against reads.
+    fn check_readable(&self) {
+        if self.prot == NoAccess {
+            panic!("SBuf is sealed against reads, use with_read");
+        }
+    }
+
+    // Fail if buffer's memory is sealed against writes.
+    fn check_writable(&self) {
+        if self.prot != ReadWrite {
+            panic!("SBuf is sealed against writes, use with_write");
+        }
+    }
+
+    /// Work with `self` as a slice. Fails if the buffer is sealed with
+    /// `seal_noaccess`.
     pub fn as_slice(&self) -> &[T] {
+        self.check_readable();
         unsafe {
-            mem::transmute(Slice {
-                data: self.as_ptr(),
-                len: self.len
-            })
+            self.raw_slice()
         }
     }
 
-    /// Work with `self` as a mutable slice.
+    /// Work with `self` as a mutable slice. Fails if the buffer is
+    /// sealed.
     pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.check_writable();
         unsafe {
-            mem::transmute(Slice {
-                data: self.as_mut_ptr() as *const T,
-                len: self.len
-            })
+            self.raw_mut_slice()
         }
     }
 
     /// Cast `self` with another type and return a slice on it.
     pub fn as_cast<U>(&self) -> &[U] {
+        self.check_readable();
         let bytes_size = self.size();
         let dst_type_size = mem::size_of::<U>();
         assert!(bytes_size > 0 && bytes_size % dst_type_size == 0);
@@ -461,6 +1044,7 @@ impl<A: Allocator, T> SBuf<A, T> {
 
     /// Cast `self` with another type and return a mut slice on it.
     pub fn as_mut_cast<U>(&mut self) -> &mut [U] {
+        self.check_writable();
         let bytes_size = self.size();
         let dst_type_size = mem::size_of::<U>();
         assert!(bytes_size > 0 && bytes_size % dst_type_size == 0);
@@ -548,6 +1132,118 @@ impl<A: Allocator, T> SBuf<A, T> {
     pub fn reverse(&mut self) {
         self[mut].reverse()
     }
+
+    /// Return the current protection of buffer's memory.
+    pub fn protection(&self) -> Protection {
+        self.prot
+    }
+
+    fn protect(&self, prot: Protection) -> Result<(), SBufError> {
+        if self.len == 0 || self.ptr.is_null() {
+            return Ok(());
+        }
+
+        let allocator: A = Allocator::new();
+        unsafe {
+            allocator.protect(self.ptr as *mut u8, self.size(),
+                              mem::min_align_of::<T>(), prot)
+        }
+    }
+
+    fn set_protection(&mut self, prot: Protection) -> Result<(), SBufError> {
+        try!(self.protect(prot));
+        self.prot = prot;
+        Ok(())
+    }
+
+    /// Make buffer's memory read-only, any write attempt crashes the
+    /// process until `unseal` is called. Only supported by allocators
+    /// placing each buffer on its own pages (e.g. `GuardedHeapAllocator`).
+    pub fn seal_readonly(&mut self) -> Result<(), SBufError> {
+        self.set_protection(ReadOnly)
+    }
+
+    /// Make buffer's memory inaccessible, any access attempt crashes the
+    /// process until `unseal` is called. Same restrictions than
+    /// `seal_readonly`.
+    pub fn seal_noaccess(&mut self) -> Result<(), SBufError> {
+        self.set_protection(NoAccess)
+    }
+
+    /// Make buffer's memory readable and writable again.
+    pub fn unseal(&mut self) -> Result<(), SBufError> {
+        self.set_protection(ReadWrite)
+    }
+
+    /// Return a wrapper formatting buffer's content with `Show`, by
+    /// default the content of a buffer is never formatted. Only meant
+    /// for debugging.
+    pub fn expose_secret_display<'a>(&'a self) -> ExposedSBuf<'a, A, T> {
+        ExposedSBuf {
+            buf: self
+        }
+    }
+
+    /// Call `f` with buffer's content temporarily made readable, the
+    /// sealed protection is restored when the outermost scoped access
+    /// returns. Fails if the protection can't be changed.
+    pub fn with_read<R>(&self, f: |&[T]| -> R) -> R {
+        let _guard = ProtectGuard::new(self, ReadOnly);
+        f(unsafe { self.raw_slice() })
+    }
+
+    /// Call `f` with buffer's content temporarily made readable and
+    /// writable, the sealed protection is restored when `f` returns.
+    /// Fails if the protection can't be changed.
+    pub fn with_write<R>(&mut self, f: |&mut [T]| -> R) -> R {
+        let _guard = ProtectGuard::new(self, ReadWrite);
+        f(unsafe { self.raw_mut_slice() })
+    }
+}
+
+// Temporarily lift the protection of a sealed buffer for a scoped
+// access. Accesses may be nested (e.g. `clone` called from a `with_read`
+// closure), only the outermost one changes the protection and its
+// sealed protection is restored on drop, including on task failure.
+struct ProtectGuard<A, T> {
+    ptr: *const SBuf<A, T>,
+    restore: bool
+}
+
+impl<A: Allocator, T> ProtectGuard<A, T> {
+    fn new(buf: &SBuf<A, T>, prot: Protection) -> ProtectGuard<A, T> {
+        let depth = buf.access.get();
+        let lift = depth == 0 && match (buf.prot, prot) {
+            (ReadWrite, _) | (ReadOnly, ReadOnly) => false,
+            _ => true
+        };
+
+        if lift {
+            match buf.protect(prot) {
+                Ok(()) => (),
+                Err(err) => panic!("{}", err)
+            }
+        }
+        buf.access.set(depth + 1);
+        ProtectGuard {
+            ptr: buf as *const SBuf<A, T>,
+            restore: lift
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<A: Allocator, T> Drop for ProtectGuard<A, T> {
+    fn drop(&mut self) {
+        let buf = unsafe { &*self.ptr };
+        buf.access.set(buf.access.get() - 1);
+        if self.restore {
+            match buf.protect(buf.prot) {
+                Ok(()) => (),
+                Err(err) => panic!("{}", err)
+            }
+        }
+    }
 }
 
 impl<A: Allocator, T: FromPrimitive> SBuf<A, T> {
@@ -563,10 +1259,155 @@ impl<A: Allocator, T: FromPrimitive> SBuf<A, T> {
     }
 }
 
+// Raw file descriptor closed when dropped.
+struct FileDesc {
+    fd: c_int
+}
+
+impl Drop for FileDesc {
+    fn drop(&mut self) {
+        unsafe {
+            unistd::close(self.fd);
+        }
+    }
+}
+
+fn errno_io_error() -> IoError {
+    IoError::from_errno(os::errno() as uint, false)
+}
+
+fn sbuf_io_error(err: SBufError) -> IoError {
+    IoError {
+        kind: io::OtherIoError,
+        desc: "secure memory allocation failed",
+        detail: Some(format!("{}", err))
+    }
+}
+
+fn load_file<A: Allocator>(path: &Path,
+                           require_private: bool) -> IoResult<SBuf<A, u8>> {
+    let fd = unsafe {
+        fcntl::open(path.to_c_str().as_ptr(),
+                    O_RDONLY | impcloexec::O_CLOEXEC, 0)
+    };
+    if fd < 0 {
+        return Err(errno_io_error());
+    }
+    let file = FileDesc {
+        fd: fd
+    };
+
+    let mut st: stat = unsafe { mem::zeroed() };
+    let ret = unsafe { stat_::fstat(file.fd, &mut st) };
+    if ret != 0 {
+        return Err(errno_io_error());
+    }
+    if (st.st_mode as c_int & S_IFMT) != S_IFREG {
+        return Err(IoError {
+            kind: io::InvalidInput,
+            desc: "not a regular file",
+            detail: None
+        });
+    }
+    if require_private && (st.st_mode as uint & 0o077) != 0 {
+        return Err(IoError {
+            kind: io::PermissionDenied,
+            desc: "secret file is accessible by group or others",
+            detail: Some(format!("mode {:o}", st.st_mode as uint & 0o777))
+        });
+    }
+
+    let size = st.st_size as uint;
+    let mut buf: SBuf<A, u8> = match SBuf::try_new_zero(size) {
+        Ok(buf) => buf,
+        Err(err) => return Err(sbuf_io_error(err))
+    };
+
+    // Read straight into the buffer, the file may have been truncated
+    // since fstat.
+    let mut pos = 0u;
+    while pos < size {
+        let ret = unsafe {
+            let dst = buf.as_mut_ptr().offset(pos as int);
+            unistd::read(file.fd, dst as *mut c_void, (size - pos) as size_t)
+        };
+        if ret < 0 {
+            if os::errno() == EINTR as int {
+                continue;
+            }
+            return Err(errno_io_error());
+        }
+        if ret == 0 {
+            return Err(IoError {
+                kind: io::EndOfFile,
+                desc: "file truncated while being read",
+                detail: None
+            });
+        }
+        pos += ret as uint;
+    }
+    Ok(buf)
+}
+
+impl<A: Allocator> SBuf<A, u8> {
+    /// New buffer holding the content of the file at `path`.
+    ///
+    /// The file is read with `read(2)` straight into the buffer, without
+    /// any intermediate copy in user space. The copy of the file kept by
+    /// the kernel in its page cache is out of reach. Fails if the file is
+    /// truncated while being read.
+    pub fn load_file(path: &Path) -> IoResult<SBuf<A, u8>> {
+        load_file(path, false)
+    }
+
+    /// Like `load_file` but refuse the file if it is accessible by its
+    /// group or by others, i.e. if its mode is not `0600` or stricter.
+    pub fn load_private_file(path: &Path) -> IoResult<SBuf<A, u8>> {
+        load_file(path, true)
+    }
+
+    /// New buffer of `len` bytes read from `reader` straight into locked
+    /// memory, without any intermediate copy. Fails if the end of
+    /// `reader` is reached before `len` bytes are read.
+    pub fn read_exact_from<R: Reader>(reader: &mut R,
+                                      len: uint) -> IoResult<SBuf<A, u8>> {
+        let mut buf: SBuf<A, u8> = match SBuf::try_new_zero(len) {
+            Ok(buf) => buf,
+            Err(err) => return Err(sbuf_io_error(err))
+        };
+        try!(reader.read_at_least(len, buf[mut]));
+        Ok(buf)
+    }
+}
+
+impl<A: Allocator, T: utils::CtSelect> SBuf<A, T> {
+    /// Assign the content of `src` to `self` iff `choice` is `1`, in
+    /// constant-time. Buffers must have the same length.
+    pub fn ct_assign(&mut self, choice: utils::Choice, src: &SBuf<A, T>) {
+        self.with_write(|dst| {
+            src.with_read(|src| utils::ct_assign_slice(choice, dst, src))
+        })
+    }
+
+    /// Swap the contents of `self` and `other` iff `choice` is `1`, in
+    /// constant-time. Buffers must have the same length.
+    pub fn ct_swap(&mut self, choice: utils::Choice, other: &mut SBuf<A, T>) {
+        self.with_write(|x| {
+            other.with_write(|y| utils::ct_swap_slice(choice, x, y))
+        })
+    }
+}
+
 #[unsafe_destructor]
 impl<A: Allocator, T> Drop for SBuf<A, T> {
     fn drop(&mut self) {
         if self.len != 0 && !self.ptr.is_null() {
+            if self.prot != ReadWrite {
+                match self.unseal() {
+                    Ok(()) => (),
+                    Err(err) => panic!("{}", err)
+                }
+            }
             unsafe {
                 dealloc::<A, T>(self.ptr, self.len)
             }
@@ -574,9 +1415,15 @@ impl<A: Allocator, T> Drop for SBuf<A, T> {
     }
 }
 
+impl<A: Allocator, T> utils::Zeroize for SBuf<A, T> {
+    fn zeroize(&mut self) {
+        self.with_write(|s| utils::zero_memory(s))
+    }
+}
+
 impl<A: Allocator, T> Clone for SBuf<A, T> {
     fn clone(&self) -> SBuf<A, T> {
-        SBuf::from_slice(self[])
+        self.with_read(|s| SBuf::from_slice(s))
     }
 }
 
@@ -630,7 +1477,7 @@ impl<A: Allocator, T> ops::SliceMut<uint, [T]> for SBuf<A, T> {
 
 impl<A: Allocator, T> PartialEq for SBuf<A, T> {
     fn eq(&self, other: &SBuf<A, T>) -> bool {
-        utils::bytes_eq(self[], other[])
+        self.with_read(|x| other.with_read(|y| utils::bytes_eq(x, y)))
     }
 }
 
@@ -643,9 +1490,23 @@ impl<A: Allocator, T> Collection for SBuf<A, T> {
     }
 }
 
-impl<A: Allocator, T: fmt::Show> fmt::Show for SBuf<A, T> {
+impl<A: Allocator, T> fmt::Show for SBuf<A, T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self[].fmt(f)
+        let allocator: A = Allocator::new();
+        write!(f, "SBuf<{}>[{} elements, redacted]", allocator.name(),
+               self.len)
+    }
+}
+
+/// Wrapper exposing the content of a buffer to `Show`, see
+/// `SBuf::expose_secret_display`.
+pub struct ExposedSBuf<'a, A: 'a, T: 'a> {
+    buf: &'a SBuf<A, T>
+}
+
+impl<'a, A: Allocator, T: fmt::Show> fmt::Show for ExposedSBuf<'a, A, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.buf.with_read(|s| s.fmt(f))
     }
 }
 
@@ -654,7 +1515,7 @@ impl<A: Allocator,
      S: Encoder<E>,
      T: Encodable<S, E>> Encodable<S, E> for SBuf<A, T> {
     fn encode(&self, s: &mut S) -> Result<(), E> {
-        self[].encode(s)
+        self.with_read(|buf| buf.encode(s))
     }
 }
 
@@ -673,16 +1534,121 @@ impl<A: Allocator,
     }
 }
 
+/// Constant-time encoding, however the returned `String` is not
+/// zeroed-out, use `utils::to_hex` to keep the result in locked memory.
 impl<A: Allocator> ToHex for SBuf<A, u8> {
     fn to_hex(&self) -> String {
-        self[].to_hex()
+        let mut v = Vec::from_elem(2 * self.len(), 0u8);
+        self.with_read(|buf| utils::hex_encode(buf, v[mut]));
+        unsafe {
+            string::raw::from_utf8(v)
+        }
+    }
+}
+
+
+/// Reader over the bytes of a secure buffer, like `std::io::MemReader`.
+pub struct SBufReader<A> {
+    buf: SBuf<A, u8>,
+    pos: uint
+}
+
+impl<A: Allocator> SBufReader<A> {
+    /// New reader starting at the beginning of `buf`.
+    pub fn new(buf: SBuf<A, u8>) -> SBufReader<A> {
+        SBufReader {
+            buf: buf,
+            pos: 0
+        }
+    }
+
+    /// Return the position of the cursor.
+    pub fn position(&self) -> uint {
+        self.pos
+    }
+
+    /// Return `true` iff all the bytes were read.
+    pub fn eof(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    /// Convert `self` into its underlying buffer.
+    pub fn into_inner(self) -> SBuf<A, u8> {
+        self.buf
+    }
+}
+
+impl<A: Allocator> Reader for SBufReader<A> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+        if self.eof() {
+            return Err(io::standard_error(io::EndOfFile));
+        }
+        let n = cmp::min(buf.len(), self.buf.len() - self.pos);
+        utils::copy_slice_memory(buf, self.buf[self.pos..], n);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Writer into a secure buffer of fixed length, like `std::io::BufWriter`.
+pub struct SBufWriter<A> {
+    buf: SBuf<A, u8>,
+    pos: uint
+}
+
+impl<A: Allocator> SBufWriter<A> {
+    /// New writer overwriting `buf` from its beginning.
+    pub fn new(buf: SBuf<A, u8>) -> SBufWriter<A> {
+        SBufWriter {
+            buf: buf,
+            pos: 0
+        }
+    }
+
+    /// Return the position of the cursor.
+    pub fn position(&self) -> uint {
+        self.pos
+    }
+
+    /// Convert `self` into its underlying buffer.
+    pub fn into_inner(self) -> SBuf<A, u8> {
+        self.buf
+    }
+}
+
+impl<A: Allocator> Writer for SBufWriter<A> {
+    /// Nothing is written and an error is returned if `buf` does not fit
+    /// in the remaining space.
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        if buf.len() > self.buf.len() - self.pos {
+            return Err(IoError {
+                kind: io::OtherIoError,
+                desc: "trying to write past end of buffer",
+                detail: None
+            });
+        }
+        let pos = self.pos;
+        utils::copy_slice_memory(self.buf[mut pos..], buf, buf.len());
+        self.pos += buf.len();
+        Ok(())
     }
 }
 
 
 #[cfg(test)]
 mod test {
-    use sbuf::{StdHeapAllocator, SBuf};
+    use std::intrinsics;
+    use std::io::{mod, File, IoResult, MemReader, TempDir};
+    use std::io::fs;
+    use std::os;
+    use std::rand::SeedableRng;
+    use std::uint;
+
+    use sbuf::{Allocator, StdHeapAllocator, GuardedHeapAllocator,
+               PooledGuardedAllocator, SBuf, SBufError, LengthOverflow,
+               Unsupported, ReadWrite, ReadOnly, NoAccess, SBufReader,
+               SBufWriter, Pool};
+    use utils::{ChaChaRng, Choice};
 
 
     #[test]
@@ -709,4 +1675,251 @@ mod test {
         };
         assert!(d == c);
     }
+
+    #[test]
+    fn test_try_new() {
+        let a: Result<SBuf<GuardedHeapAllocator, u64>, SBufError> =
+            SBuf::try_new_zero(uint::MAX / 2);
+        assert!(a.err() == Some(LengthOverflow));
+        let a: Result<SBuf<GuardedHeapAllocator, u8>, SBufError> =
+            SBuf::try_new_zero(uint::MAX - 8);
+        assert!(a.err() == Some(LengthOverflow));
+
+        let b: Result<SBuf<GuardedHeapAllocator, u8>, SBufError> =
+            SBuf::try_from_slice([42u8, ..64][]);
+        let b = b.ok().expect("allocation failed");
+        assert!(b[] == [42u8, ..64][]);
+
+        let c: Result<SBuf<GuardedHeapAllocator, u32>, SBufError> =
+            SBuf::try_from_fn(16, |i| i as u32);
+        let c = c.ok().expect("allocation failed");
+        for i in range(0u, 16) {
+            assert_eq!(c[i], i as u32);
+        }
+    }
+
+    #[test]
+    fn test_pooled() {
+        let mut v: Vec<SBuf<PooledGuardedAllocator, u8>> = Vec::new();
+        for i in range(0u, 1024) {
+            v.push(SBuf::from_fn(32, |_| i as u8));
+        }
+        for (i, b) in v.iter().enumerate() {
+            assert!(b.iter().all(|&x| x == i as u8));
+        }
+
+        let large: SBuf<PooledGuardedAllocator, u64> = SBuf::new_zero(4096);
+        assert!(large.iter().all(|&x| x == 0));
+
+        // Slots remain mapped once released and must be zeroed-out. A
+        // private pool is used, the slots of the process-wide one may be
+        // reused by concurrent tests.
+        let mut pool = Pool::new();
+        unsafe {
+            let ptr = pool.allocate(64).ok().expect("allocation failed");
+            for i in range(0i, 64) {
+                *ptr.offset(i) = 42;
+            }
+            pool.deallocate(ptr);
+            for i in range(0i, 64) {
+                let b = intrinsics::volatile_load(ptr.offset(i) as *const u8);
+                assert_eq!(b, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seal() {
+        let mut a: SBuf<GuardedHeapAllocator, u8> = SBuf::new_zero(100);
+        assert!(a.seal_readonly().is_ok());
+        assert_eq!(a.protection(), ReadOnly);
+        assert!(a.iter().all(|&x| x == 0));
+
+        a.with_write(|s| s[42] = 42);
+        assert_eq!(a.protection(), ReadOnly);
+        assert_eq!(a[42], 42);
+
+        assert!(a.seal_noaccess().is_ok());
+        assert_eq!(a.protection(), NoAccess);
+        assert_eq!(a.with_read(|s| s[42]), 42);
+        a.with_write(|s| s[0] = 1);
+
+        assert!(a.unseal().is_ok());
+        assert_eq!(a.protection(), ReadWrite);
+        assert_eq!(a[0], 1);
+        a[1] = 2;
+
+        let mut b: SBuf<PooledGuardedAllocator, u8> = SBuf::new_zero(32);
+        assert!(b.seal_noaccess().err() == Some(Unsupported));
+        assert_eq!(b.protection(), ReadWrite);
+    }
+
+    #[test]
+    fn test_sealed_access() {
+        let mut a: SBuf<GuardedHeapAllocator, u8> = SBuf::from_fn(64, |i| i as u8);
+        let b = a.clone();
+        assert!(a.seal_noaccess().is_ok());
+
+        // Comparisons and nested scoped accesses lift the protection.
+        assert!(a == b);
+        assert_eq!(a.with_read(|s| {
+            let c = a.clone();
+            assert!(c == b);
+            s[10]
+        }), 10);
+        assert_eq!(a.with_read(|s| s[11]), 11);
+        assert_eq!(a.protection(), NoAccess);
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_sealed_index() {
+        let mut a: SBuf<GuardedHeapAllocator, u8> = SBuf::new_zero(64);
+        assert!(a.seal_readonly().is_ok());
+        a[0] = 1;
+    }
+
+    #[test]
+    fn test_guarded_alignment() {
+        let a: SBuf<GuardedHeapAllocator, u8> = SBuf::new_rand(100);
+        assert_eq!((a.as_ptr() as uint + a.len()) % os::page_size(), 0);
+
+        let b: SBuf<GuardedHeapAllocator, u64> = SBuf::new_zero(13);
+        assert_eq!(b.as_ptr() as uint % 8, 0);
+        assert!(b.iter().all(|&x| x == 0));
+
+        let c = a.clone();
+        assert!(a == c);
+    }
+
+    #[test]
+    fn test_show() {
+        let a: SBuf<GuardedHeapAllocator, u8> = SBuf::from_slice([1u8, 2, 3][]);
+        assert_eq!(format!("{}", a).as_slice(),
+                   "SBuf<GuardedHeapAllocator>[3 elements, redacted]");
+        assert_eq!(format!("{}", a.expose_secret_display()).as_slice(),
+                   "[1, 2, 3]");
+    }
+
+    static mut ARENA: [u64, ..32] = [0, ..32];
+
+    // Allocator serving a static arena left untouched on deallocation,
+    // so that wiping can be observed once a buffer is dropped.
+    struct ArenaAllocator;
+
+    impl Allocator for ArenaAllocator {
+        fn new() -> ArenaAllocator {
+            ArenaAllocator
+        }
+
+        unsafe fn allocate(&self, size: uint, _: uint) -> *mut u8 {
+            assert!(size <= ARENA.len() * 8);
+            ARENA.as_mut_ptr() as *mut u8
+        }
+
+        unsafe fn deallocate(&self, _: *mut u8, _: uint, _: uint) {
+        }
+
+        fn is_locked(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_wipe_on_drop() {
+        {
+            let a: SBuf<ArenaAllocator, u64> = SBuf::from_fn(32, |i| {
+                0xdeadbeef + i as u64
+            });
+            assert!(a.iter().all(|&x| x != 0));
+        }
+
+        let arena = unsafe { ARENA.as_ptr() };
+        for i in range(0i, 32) {
+            assert_eq!(unsafe { intrinsics::volatile_load(arena.offset(i)) },
+                       0);
+        }
+    }
+
+    #[test]
+    fn test_ct_select() {
+        let mut a: SBuf<GuardedHeapAllocator, u32> = SBuf::new_zero(8);
+        let mut b: SBuf<GuardedHeapAllocator, u32> = SBuf::from_fn(8, |i| i as u32);
+        let c = b.clone();
+
+        a.ct_swap(Choice::new(0), &mut b);
+        assert!(b == c);
+        a.ct_swap(Choice::new(1), &mut b);
+        assert!(a == c);
+        b.ct_assign(Choice::new(1), &a);
+        assert!(b == c);
+    }
+
+    #[test]
+    fn test_new_rand_with() {
+        let mut r1: ChaChaRng = SeedableRng::from_seed([7u8, ..32][]);
+        let mut r2: ChaChaRng = SeedableRng::from_seed([7u8, ..32][]);
+        let a: SBuf<GuardedHeapAllocator, u64> = SBuf::new_rand_with(64, &mut r1);
+        let b: SBuf<GuardedHeapAllocator, u64> = SBuf::new_rand_with(64, &mut r2);
+        assert!(a == b);
+    }
+
+    #[test]
+    fn test_load_file() {
+        let dir = TempDir::new("sbuf").unwrap();
+        let path = dir.path().join("key");
+        File::create(&path).write(b"secret key material").unwrap();
+
+        fs::chmod(&path, io::USER_READ | io::USER_WRITE).unwrap();
+        let a: SBuf<GuardedHeapAllocator, u8> =
+            SBuf::load_private_file(&path).unwrap();
+        assert!(a[] == b"secret key material");
+
+        fs::chmod(&path, io::USER_READ | io::USER_WRITE |
+                  io::GROUP_READ).unwrap();
+        let b: SBuf<PooledGuardedAllocator, u8> =
+            SBuf::load_file(&path).unwrap();
+        assert!(b[] == a[]);
+        let c: IoResult<SBuf<PooledGuardedAllocator, u8>> =
+            SBuf::load_private_file(&path);
+        assert_eq!(c.err().unwrap().kind, io::PermissionDenied);
+
+        File::create(&path).unwrap();
+        let d: SBuf<GuardedHeapAllocator, u8> = SBuf::load_file(&path).unwrap();
+        assert_eq!(d.len(), 0);
+
+        let e: IoResult<SBuf<GuardedHeapAllocator, u8>> =
+            SBuf::load_file(dir.path());
+        assert!(e.is_err());
+        let f: IoResult<SBuf<GuardedHeapAllocator, u8>> =
+            SBuf::load_file(&dir.path().join("missing"));
+        assert!(f.is_err());
+    }
+
+    #[test]
+    fn test_reader_writer() {
+        let mut r = MemReader::new(b"secret key".to_vec());
+        let a: SBuf<PooledGuardedAllocator, u8> =
+            SBuf::read_exact_from(&mut r, 6).unwrap();
+        assert!(a[] == b"secret");
+        let b: IoResult<SBuf<PooledGuardedAllocator, u8>> =
+            SBuf::read_exact_from(&mut r, 5);
+        assert!(b.is_err());
+
+        let mut w = SBufWriter::new(SBuf::new_zero(10));
+        w.write(a[]).unwrap();
+        w.write(b" key").unwrap();
+        assert_eq!(w.position(), 10);
+        assert!(w.write(b"!").is_err());
+        let c: SBuf<PooledGuardedAllocator, u8> = w.into_inner();
+        assert!(c[] == b"secret key");
+
+        let mut r = SBufReader::new(c);
+        assert!(r.read_exact(3).unwrap()[] == b"sec");
+        let mut d = [0u8, ..16];
+        assert_eq!(r.read(d).unwrap(), 7);
+        assert!(d[..7] == b"ret key");
+        assert!(r.eof());
+        assert!(r.read(d).is_err());
+    }
 }
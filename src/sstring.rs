@@ -0,0 +1,189 @@
+//! Secure String
+use std::fmt;
+use std::io::{mod, IoError, IoResult, Reader};
+use std::io::stdio;
+use std::str;
+
+use sbuf::{Allocator, SBuf};
+use svec::SVec;
+use utils;
+
+
+/// Secure UTF-8 string, intended to hold passphrases and PINs.
+///
+/// Its bytes are stored in locked memory through a `SVec<A, u8>`, they
+/// are never formatted by `Show` and compared in constant time.
+pub struct SString<A> {
+    vec: SVec<A, u8>
+}
+
+impl<A: Allocator> SString<A> {
+    /// New empty string.
+    pub fn new() -> SString<A> {
+        SString {
+            vec: SVec::new()
+        }
+    }
+
+    /// New string copied from `s`.
+    pub fn from_str(s: &str) -> SString<A> {
+        SString {
+            vec: SVec::from_slice(s.as_bytes())
+        }
+    }
+
+    /// New string from a secure buffer of bytes. Return the buffer back
+    /// if it is not valid UTF-8.
+    pub fn from_utf8(buf: SBuf<A, u8>) -> Result<SString<A>, SBuf<A, u8>> {
+        if !str::is_utf8(buf[]) {
+            return Err(buf);
+        }
+        Ok(SString {
+            vec: SVec::from_sbuf(buf)
+        })
+    }
+
+    /// New string from a secure buffer of bytes without checking that it
+    /// is valid UTF-8.
+    pub unsafe fn from_utf8_unchecked(buf: SBuf<A, u8>) -> SString<A> {
+        SString {
+            vec: SVec::from_sbuf(buf)
+        }
+    }
+
+    /// Read a line from `reader` directly into locked memory, bytes are
+    /// read one by one to avoid any intermediate copy, hence `reader`
+    /// should not be buffered. The trailing newline is not included.
+    pub fn read_line<R: Reader>(reader: &mut R) -> IoResult<SString<A>> {
+        let mut vec: SVec<A, u8> = SVec::with_capacity(64);
+        loop {
+            match reader.read_byte() {
+                Ok(b'\n') => break,
+                Ok(b) => vec.push(b),
+                Err(ref err) if err.kind == io::EndOfFile && vec.len() > 0 => {
+                    break
+                }
+                Err(err) => return Err(err)
+            }
+        }
+
+        let len = vec.len();
+        if len > 0 && vec[len - 1] == b'\r' {
+            vec.truncate(len - 1);
+        }
+
+        if !str::is_utf8(vec[]) {
+            return Err(IoError {
+                kind: io::InvalidInput,
+                desc: "invalid UTF-8 input",
+                detail: None
+            });
+        }
+        Ok(SString {
+            vec: vec
+        })
+    }
+
+    /// Read a line from unbuffered standard input, see `read_line`.
+    pub fn read_line_stdin() -> IoResult<SString<A>> {
+        SString::read_line(&mut stdio::stdin_raw())
+    }
+
+    /// Append `s` to the end of the string.
+    pub fn push_str(&mut self, s: &str) {
+        self.vec.extend_from_slice(s.as_bytes())
+    }
+
+    /// Remove and zero-out the content of the string.
+    pub fn clear(&mut self) {
+        self.vec.clear()
+    }
+
+    /// Work with `self` as a string slice.
+    pub fn as_str(&self) -> &str {
+        unsafe {
+            str::raw::from_utf8(self.vec[])
+        }
+    }
+
+    /// Work with `self` as a slice of bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.vec[]
+    }
+}
+
+impl<A: Allocator> Clone for SString<A> {
+    fn clone(&self) -> SString<A> {
+        SString {
+            vec: self.vec.clone()
+        }
+    }
+}
+
+impl<A: Allocator> PartialEq for SString<A> {
+    fn eq(&self, other: &SString<A>) -> bool {
+        utils::bytes_eq(self.vec[], other.vec[])
+    }
+}
+
+impl<A: Allocator> Eq for SString<A> {
+}
+
+impl<A: Allocator> Collection for SString<A> {
+    fn len(&self) -> uint {
+        self.vec.len()
+    }
+}
+
+impl<A: Allocator> fmt::Show for SString<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SString(***)")
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::{IoResult, MemReader};
+
+    use sbuf::{GuardedHeapAllocator, SBuf};
+    use sstring::SString;
+
+
+    #[test]
+    fn test_basic() {
+        let mut s: SString<GuardedHeapAllocator> = SString::from_str("pass");
+        s.push_str("phrase");
+        assert_eq!(s.as_str(), "passphrase");
+        assert_eq!(s.len(), 10);
+        assert_eq!(format!("{}", s).as_slice(), "SString(***)");
+
+        let t: SString<GuardedHeapAllocator> = SString::from_str("passphrase");
+        assert!(s == t);
+        s.clear();
+        assert!(s != t);
+    }
+
+    #[test]
+    fn test_from_utf8() {
+        let b: SBuf<GuardedHeapAllocator, u8> = SBuf::from_slice(b"\xe2\x82\xac");
+        let s = SString::from_utf8(b).ok().expect("valid UTF-8");
+        assert_eq!(s.as_str(), "€");
+
+        let b: SBuf<GuardedHeapAllocator, u8> = SBuf::from_slice(b"\xff\xfe");
+        assert!(SString::from_utf8(b).is_err());
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut r = MemReader::new(b"secret\r\nnext\nlast".to_vec());
+        let a: SString<GuardedHeapAllocator> = SString::read_line(&mut r).unwrap();
+        assert_eq!(a.as_str(), "secret");
+        let b: SString<GuardedHeapAllocator> = SString::read_line(&mut r).unwrap();
+        assert_eq!(b.as_str(), "next");
+        let c: SString<GuardedHeapAllocator> = SString::read_line(&mut r).unwrap();
+        assert_eq!(c.as_str(), "last");
+        let d: IoResult<SString<GuardedHeapAllocator>> = SString::read_line(&mut r);
+        assert!(d.is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Secure Vector
+use std::cmp;
+use std::mem;
+use std::ops;
+use std::ptr;
+use std::slice::{Items, MutItems};
+use std::uint;
+
+use sbuf::{Allocator, SBuf, SBufError, LengthOverflow};
+use utils;
+
+
+/// Secure growable vector.
+///
+/// Its elements are stored in a `SBuf`, each time its capacity must be
+/// increased a new buffer is allocated, the elements are copied into it
+/// and the previous buffer is zeroed-out and released. Elements removed
+/// by `truncate` or `clear` are zeroed-out too.
+pub struct SVec<A, T> {
+    buf: SBuf<A, T>,
+    len: uint
+}
+
+impl<A: Allocator, T> SVec<A, T> {
+    /// New empty vector, no memory is allocated until elements are
+    /// pushed.
+    pub fn new() -> SVec<A, T> {
+        SVec::with_capacity(0)
+    }
+
+    /// New empty vector with space for at least `capacity` elements.
+    pub fn with_capacity(capacity: uint) -> SVec<A, T> {
+        SVec {
+            buf: SBuf::new_zero(capacity),
+            len: 0
+        }
+    }
+
+    /// New vector initialized from slice.
+    pub fn from_slice(values: &[T]) -> SVec<A, T> {
+        SVec {
+            buf: SBuf::from_slice(values),
+            len: values.len()
+        }
+    }
+
+    /// New vector taking ownership of `buf`, its elements become the
+    /// elements of the vector.
+    pub fn from_sbuf(buf: SBuf<A, T>) -> SVec<A, T> {
+        let len = buf.len();
+        SVec {
+            buf: buf,
+            len: len
+        }
+    }
+
+    /// Return the number of elements the vector can hold without
+    /// reallocating.
+    pub fn capacity(&self) -> uint {
+        if mem::size_of::<T>() == 0 {
+            uint::MAX
+        } else {
+            self.buf.len()
+        }
+    }
+
+    /// Reserve capacity for at least `additional` more elements. Fails
+    /// if the allocation failed.
+    pub fn reserve(&mut self, additional: uint) {
+        match self.try_reserve(additional) {
+            Ok(()) => (),
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    /// Like `reserve` but return an error if the allocation failed.
+    pub fn try_reserve(&mut self, additional: uint) -> Result<(), SBufError> {
+        let required = match self.len.checked_add(&additional) {
+            Some(required) => required,
+            None => return Err(LengthOverflow)
+        };
+        if required <= self.capacity() {
+            return Ok(());
+        }
+
+        let capacity = cmp::max(required, self.capacity() * 2);
+        let mut buf: SBuf<A, T> = try!(SBuf::try_new_zero(capacity));
+        unsafe {
+            ptr::copy_nonoverlapping_memory(buf.as_mut_ptr(),
+                                            self.buf.as_ptr(),
+                                            self.len);
+        }
+        // The previous buffer is zeroed-out when dropped.
+        self.buf = buf;
+        Ok(())
+    }
+
+    /// Append `value` to the end of the vector.
+    pub fn push(&mut self, value: T) {
+        self.reserve(1);
+        unsafe {
+            ptr::write(self.buf.as_mut_ptr().offset(self.len as int), value);
+        }
+        self.len += 1;
+    }
+
+    /// Append all the elements of `values` to the end of the vector.
+    pub fn extend_from_slice(&mut self, values: &[T]) {
+        self.reserve(values.len());
+        unsafe {
+            ptr::copy_nonoverlapping_memory(
+                self.buf.as_mut_ptr().offset(self.len as int),
+                values.as_ptr(),
+                values.len());
+        }
+        self.len += values.len();
+    }
+
+    /// Shorten the vector to `len` elements, removed elements are
+    /// zeroed-out. Has no effect if `len` is greater than the current
+    /// length.
+    pub fn truncate(&mut self, len: uint) {
+        if len < self.len {
+            utils::zero_memory(self.buf.slice_mut(len, self.len));
+            self.len = len;
+        }
+    }
+
+    /// Remove and zero-out all the elements, the capacity is unchanged.
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    /// Convert `self` into a `SBuf` of exactly `len()` elements.
+    pub fn into_sbuf(self) -> SBuf<A, T> {
+        if self.len == self.buf.len() {
+            self.buf
+        } else {
+            SBuf::from_slice(self[])
+        }
+    }
+
+    /// Work with `self` as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        self.buf.slice_to(self.len)
+    }
+
+    /// Work with `self` as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.buf.slice_to_mut(self.len)
+    }
+
+    /// Return an iterator over references to the elements of the vector
+    /// in order.
+    pub fn iter(&self) -> Items<T> {
+        self[].iter()
+    }
+
+    /// Return an iterator over mutable references to the elements of the
+    /// vector in order.
+    pub fn iter_mut(&mut self) -> MutItems<T> {
+        self[mut].iter_mut()
+    }
+}
+
+impl<A: Allocator, T> Clone for SVec<A, T> {
+    fn clone(&self) -> SVec<A, T> {
+        SVec::from_slice(self[])
+    }
+}
+
+impl<A: Allocator, T> Index<uint, T> for SVec<A, T> {
+    fn index(&self, index: &uint) -> &T {
+        &self.as_slice()[*index]
+    }
+}
+
+impl<A: Allocator, T> IndexMut<uint, T> for SVec<A, T> {
+    fn index_mut(&mut self, index: &uint) -> &mut T {
+        &mut self.as_mut_slice()[*index]
+    }
+}
+
+impl<A: Allocator, T> ops::Slice<uint, [T]> for SVec<A, T> {
+    fn as_slice_<'a>(&'a self) -> &'a [T] {
+        self.as_slice()
+    }
+
+    fn slice_from_or_fail<'a>(&'a self, start: &uint) -> &'a [T] {
+        self.as_slice().slice_from_or_fail(start)
+    }
+
+    fn slice_to_or_fail<'a>(&'a self, end: &uint) -> &'a [T] {
+        self.as_slice().slice_to_or_fail(end)
+    }
+
+    fn slice_or_fail<'a>(&'a self, start: &uint, end: &uint) -> &'a [T] {
+        self.as_slice().slice_or_fail(start, end)
+    }
+}
+
+impl<A: Allocator, T> ops::SliceMut<uint, [T]> for SVec<A, T> {
+    fn as_mut_slice_<'a>(&'a mut self) -> &'a mut [T] {
+        self.as_mut_slice()
+    }
+
+    fn slice_from_or_fail_mut<'a>(&'a mut self, start: &uint) -> &'a mut [T] {
+        self.as_mut_slice().slice_from_or_fail_mut(start)
+    }
+
+    fn slice_to_or_fail_mut<'a>(&'a mut self, end: &uint) -> &'a mut [T] {
+        self.as_mut_slice().slice_to_or_fail_mut(end)
+    }
+
+    fn slice_or_fail_mut<'a>(&'a mut self, start: &uint, end: &uint) -> &'a mut [T] {
+        self.as_mut_slice().slice_or_fail_mut(start, end)
+    }
+}
+
+impl<A: Allocator, T> PartialEq for SVec<A, T> {
+    fn eq(&self, other: &SVec<A, T>) -> bool {
+        utils::bytes_eq(self[], other[])
+    }
+}
+
+impl<A: Allocator, T> Eq for SVec<A, T> {
+}
+
+impl<A: Allocator, T> Collection for SVec<A, T> {
+    fn len(&self) -> uint {
+        self.len
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use sbuf::{GuardedHeapAllocator, PooledGuardedAllocator, SBuf};
+    use svec::SVec;
+
+
+    #[test]
+    fn test_push() {
+        let mut v: SVec<PooledGuardedAllocator, u32> = SVec::new();
+        for i in range(0u, 1000) {
+            v.push(i as u32);
+        }
+        assert_eq!(v.len(), 1000);
+        assert!(v.capacity() >= 1000);
+        for i in range(0u, 1000) {
+            assert_eq!(v[i], i as u32);
+        }
+    }
+
+    #[test]
+    fn test_extend_truncate() {
+        let mut v: SVec<GuardedHeapAllocator, u8> = SVec::with_capacity(4);
+        v.extend_from_slice(b"secret");
+        v.extend_from_slice(b" key");
+        assert!(v[] == b"secret key");
+
+        v.truncate(6);
+        assert!(v[] == b"secret");
+        v.truncate(42);
+        assert_eq!(v.len(), 6);
+
+        let capacity = v.capacity();
+        v.clear();
+        assert_eq!(v.len(), 0);
+        assert_eq!(v.capacity(), capacity);
+
+        v.extend_from_slice(b"key");
+        let b: SBuf<GuardedHeapAllocator, u8> = v.into_sbuf();
+        assert!(b[] == b"key");
+    }
+}
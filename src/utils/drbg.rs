@@ -0,0 +1,300 @@
+//! HMAC-DRBG
+//!
+//! Deterministic random bit generator of NIST SP 800-90A section 10.1.2
+//! instantiated with HMAC-SHA-256, without prediction resistance. Its
+//! internal state `K || V` is stored in a `SBuf`, thus locked in memory
+//! and zeroed-out when the generator is dropped.
+use std::cmp;
+use std::fmt;
+use std::rand::Rng;
+
+use sbuf::{Allocator, SBuf, SBufError};
+use utils;
+use utils::sha256::{Hmac, DIGEST_LEN};
+
+
+/// Security strength in bytes.
+pub static SECURITY_STRENGTH: uint = 32;
+
+/// Maximum number of `generate` requests between two reseeds.
+pub static RESEED_INTERVAL: u64 = 1 << 48;
+
+/// Maximum number of bytes returned by a single `generate` request.
+pub static MAX_REQUEST_LEN: uint = 1 << 16;
+
+/// Errors returned by the DRBG.
+#[deriving(Clone, PartialEq, Eq)]
+pub enum DrbgError {
+    /// The entropy input is shorter than the security strength, or the
+    /// entropy input and the nonce are together shorter than 1.5 times
+    /// the security strength.
+    InsufficientEntropy,
+    /// More than `MAX_REQUEST_LEN` bytes were requested.
+    RequestTooLarge,
+    /// The reseed interval is exhausted, `reseed` must be called.
+    ReseedRequired,
+    /// The allocation of the internal state failed.
+    Alloc(SBufError)
+}
+
+impl fmt::Show for DrbgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InsufficientEntropy => write!(f, "insufficient entropy input"),
+            RequestTooLarge => write!(f, "too many bytes requested"),
+            ReseedRequired => write!(f, "reseed required"),
+            Alloc(ref err) => write!(f, "state allocation failed: {}", err)
+        }
+    }
+}
+
+
+/// HMAC-DRBG with SHA-256.
+///
+/// Entropy inputs must come from an approved entropy source, e.g.
+/// `utils::SysRng`. `generate` fails once `RESEED_INTERVAL` requests
+/// were served since the last (re)seeding.
+pub struct HmacDrbg<A> {
+    // K || V
+    state: SBuf<A, u8>,
+    reseed_counter: u64
+}
+
+impl<A: Allocator> HmacDrbg<A> {
+    /// Instantiate a new DRBG from `entropy`, `nonce` and an optional
+    /// `personalization` string (may be empty).
+    pub fn instantiate(entropy: &[u8], nonce: &[u8], personalization: &[u8])
+                       -> Result<HmacDrbg<A>, DrbgError> {
+        if entropy.len() < SECURITY_STRENGTH ||
+            entropy.len() + nonce.len() < SECURITY_STRENGTH * 3 / 2 {
+            return Err(InsufficientEntropy);
+        }
+
+        let mut state: SBuf<A, u8> = match SBuf::try_new_zero(2 * DIGEST_LEN) {
+            Ok(state) => state,
+            Err(err) => return Err(Alloc(err))
+        };
+        for b in state.slice_from_mut(DIGEST_LEN).iter_mut() {
+            *b = 0x01;
+        }
+
+        let mut drbg = HmacDrbg {
+            state: state,
+            reseed_counter: 1
+        };
+        drbg.update(&[entropy, nonce, personalization]);
+        Ok(drbg)
+    }
+
+    /// Reseed with fresh `entropy` and optional `additional` input (may
+    /// be empty).
+    pub fn reseed(&mut self, entropy: &[u8],
+                  additional: &[u8]) -> Result<(), DrbgError> {
+        if entropy.len() < SECURITY_STRENGTH {
+            return Err(InsufficientEntropy);
+        }
+        self.update(&[entropy, additional]);
+        self.reseed_counter = 1;
+        Ok(())
+    }
+
+    /// Fill `out` with pseudo-random bytes, `additional` input (may be
+    /// empty) is mixed into the state before and after the generation.
+    pub fn generate(&mut self, out: &mut [u8],
+                    additional: &[u8]) -> Result<(), DrbgError> {
+        if out.len() > MAX_REQUEST_LEN {
+            return Err(RequestTooLarge);
+        }
+        if self.reseed_counter > RESEED_INTERVAL {
+            return Err(ReseedRequired);
+        }
+
+        if !additional.is_empty() {
+            self.update(&[additional]);
+        }
+
+        {
+            let (k, v) = self.state.split_at_mut(DIGEST_LEN);
+            let mut pos = 0u;
+            while pos < out.len() {
+                let mut mac = Hmac::new(k[]);
+                mac.input(v[]);
+                mac.result(v[mut]);
+                let n = cmp::min(DIGEST_LEN, out.len() - pos);
+                utils::copy_slice_memory(out[mut pos..], v[], n);
+                pos += n;
+            }
+        }
+
+        self.update(&[additional]);
+        self.reseed_counter += 1;
+        Ok(())
+    }
+
+    /// Return the number of `generate` requests served since the last
+    /// (re)seeding, plus one.
+    pub fn reseed_counter(&self) -> u64 {
+        self.reseed_counter
+    }
+
+    // HMAC_DRBG_Update, the provided data is the concatenation of the
+    // slices of `data`.
+    fn update(&mut self, data: &[&[u8]]) {
+        let empty = data.iter().all(|d| d.is_empty());
+        let (k, v) = self.state.split_at_mut(DIGEST_LEN);
+
+        for &sep in [0x00u8, 0x01].iter() {
+            if sep == 0x01 && empty {
+                break;
+            }
+
+            let mut mac = Hmac::new(k[]);
+            mac.input(v[]);
+            mac.input(&[sep]);
+            for d in data.iter() {
+                mac.input(*d);
+            }
+            mac.result(k[mut]);
+
+            let mut mac = Hmac::new(k[]);
+            mac.input(v[]);
+            mac.result(v[mut]);
+        }
+    }
+}
+
+impl<A: Allocator> Rng for HmacDrbg<A> {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0u8, ..4];
+        self.fill_bytes(buf);
+        utils::read_u32_le(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(MAX_REQUEST_LEN) {
+            match self.generate(chunk, []) {
+                Ok(()) => (),
+                Err(err) => panic!("HmacDrbg failed: {}", err)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use serialize::hex::FromHex;
+
+    use sbuf::{GuardedHeapAllocator, PooledGuardedAllocator};
+    use utils::drbg::{DrbgError, HmacDrbg, InsufficientEntropy,
+                      RequestTooLarge, ReseedRequired, RESEED_INTERVAL,
+                      MAX_REQUEST_LEN};
+
+
+    // NIST CAVP vectors, see the header of the file for their format.
+    static VECTORS: &'static str = include_str!("drbg_vectors.rsp");
+
+    // Return the values of the fields `name` of a test, in order.
+    fn values<'a>(fields: &'a [(String, Vec<u8>)],
+                  name: &str) -> Vec<&'a [u8]> {
+        fields.iter().filter(|&&(ref n, _)| n.as_slice() == name)
+              .map(|&(_, ref v)| v[]).collect()
+    }
+
+    // Run a test and return true iff it reseeds the DRBG.
+    fn check_vector(fields: &[(String, Vec<u8>)]) -> bool {
+        let entropy = values(fields, "EntropyInput");
+        let nonce = values(fields, "Nonce");
+        let pers = values(fields, "PersonalizationString");
+        let additional = values(fields, "AdditionalInput");
+        let expected = values(fields, "ReturnedBits");
+        assert!(entropy.len() == 1 && nonce.len() == 1 && pers.len() == 1 &&
+                additional.len() == 2 && expected.len() == 1);
+
+        let mut drbg: HmacDrbg<GuardedHeapAllocator> =
+            HmacDrbg::instantiate(entropy[0], nonce[0], pers[0]).unwrap();
+        let reseed = values(fields, "EntropyInputReseed");
+        let reseed_additional = values(fields, "AdditionalInputReseed");
+        assert_eq!(reseed.len(), reseed_additional.len());
+        if !reseed.is_empty() {
+            drbg.reseed(reseed[0], reseed_additional[0]).unwrap();
+        }
+
+        let mut out = Vec::from_elem(expected[0].len(), 0u8);
+        drbg.generate(out[mut], additional[0]).unwrap();
+        drbg.generate(out[mut], additional[1]).unwrap();
+        assert!(out[] == expected[0]);
+        !reseed.is_empty()
+    }
+
+    #[test]
+    fn test_cavp_vectors() {
+        let mut count = 0u;
+        let mut reseeds = 0u;
+        let mut fields: Vec<(String, Vec<u8>)> = Vec::new();
+        for line in VECTORS.lines() {
+            let line = line.trim();
+            if line.starts_with("#") || line.starts_with("[") ||
+                line.starts_with("COUNT") {
+                continue;
+            }
+            if line.is_empty() {
+                if !fields.is_empty() {
+                    if check_vector(fields[]) {
+                        reseeds += 1;
+                    }
+                    fields.clear();
+                    count += 1;
+                }
+                continue;
+            }
+            let mut parts = line.splitn(1, '=');
+            let name = parts.next().unwrap().trim();
+            let value = parts.next().expect("field value");
+            fields.push((String::from_str(name),
+                         value.trim().from_hex().unwrap()));
+        }
+        if !fields.is_empty() {
+            if check_vector(fields[]) {
+                reseeds += 1;
+            }
+            count += 1;
+        }
+        assert!(count > 0 && reseeds > 0);
+    }
+
+    #[test]
+    fn test_reseed() {
+        let entropy = [0x42u8, ..48];
+        let r: Result<HmacDrbg<PooledGuardedAllocator>, DrbgError> =
+            HmacDrbg::instantiate(entropy[..31], entropy[..16], []);
+        assert!(r.err() == Some(InsufficientEntropy));
+        let r: Result<HmacDrbg<PooledGuardedAllocator>, DrbgError> =
+            HmacDrbg::instantiate(entropy[..32], entropy[..15], []);
+        assert!(r.err() == Some(InsufficientEntropy));
+
+        let mut a: HmacDrbg<PooledGuardedAllocator> =
+            HmacDrbg::instantiate(entropy, [], b"a").unwrap();
+        let mut b: HmacDrbg<PooledGuardedAllocator> =
+            HmacDrbg::instantiate(entropy, [], b"a").unwrap();
+        let mut x = [0u8, ..64];
+        let mut y = [0u8, ..64];
+        a.generate(x, []).unwrap();
+        b.generate(y, []).unwrap();
+        assert!(x[] == y[]);
+        assert_eq!(a.reseed_counter(), 2);
+
+        a.generate(x, b"additional").unwrap();
+        b.generate(y, []).unwrap();
+        assert!(x[] != y[]);
+
+        a.reseed(entropy[..32], []).unwrap();
+        assert_eq!(a.reseed_counter(), 1);
+        assert!(a.reseed(entropy[..31], []).err() == Some(InsufficientEntropy));
+
+        let mut big = Vec::from_elem(MAX_REQUEST_LEN + 1, 0u8);
+        assert!(a.generate(big[mut], []).err() == Some(RequestTooLarge));
+        a.reseed_counter = RESEED_INTERVAL + 1;
+        assert!(a.generate(x, []).err() == Some(ReseedRequired));
+    }
+}
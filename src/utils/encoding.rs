@@ -0,0 +1,328 @@
+//! Constant-time RFC 4648 encodings
+//!
+//! Base64, Base64url and Base32 codecs suited to secret data: characters
+//! are mapped arithmetically, without table lookup, and the decoders
+//! never branch on nor exit early on the value of a character. Only the
+//! lengths and the position of the padding are public.
+//!
+//! Decoding is strict, only the canonical encoding of some data is
+//! accepted: no whitespace, no missing or extra padding and the unused
+//! trailing bits of the last character must be zero.
+use sbuf::{Allocator, SBuf};
+use sstring::SString;
+use utils;
+
+
+/// Encodings of RFC 4648.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Encoding {
+    /// Base64 (section 4).
+    Base64,
+    /// Base64 without padding.
+    Base64NoPad,
+    /// URL and filename safe Base64 (section 5).
+    Base64Url,
+    /// URL and filename safe Base64 without padding.
+    Base64UrlNoPad,
+    /// Base32 (section 6).
+    Base32,
+    /// Base32 without padding.
+    Base32NoPad
+}
+
+// Return the number of bits encoded by each character.
+fn char_bits(encoding: Encoding) -> uint {
+    match encoding {
+        Base32 | Base32NoPad => 5,
+        _ => 6
+    }
+}
+
+// Return the number of characters of a padded block.
+fn block_len(encoding: Encoding) -> uint {
+    match encoding {
+        Base32 | Base32NoPad => 8,
+        _ => 4
+    }
+}
+
+fn is_padded(encoding: Encoding) -> bool {
+    match encoding {
+        Base64 | Base64Url | Base32 => true,
+        _ => false
+    }
+}
+
+// Characters for values 62 and 63 in Base64 variants.
+fn specials(encoding: Encoding) -> (i16, i16) {
+    match encoding {
+        Base64Url | Base64UrlNoPad => (b'-' as i16, b'_' as i16),
+        _ => (b'+' as i16, b'/' as i16)
+    }
+}
+
+// Return 0xffff iff lo <= x <= hi; 0 otherwise. The sign bit of both
+// differences is spread over the word, arguments must be comprised
+// between -2^14 and 2^14 so that they don't overflow.
+fn range_mask(x: i16, lo: i16, hi: i16) -> i16 {
+    !(((x - lo) | (hi - x)) >> 15)
+}
+
+// Return the character encoding value `v`, without table nor branch.
+fn encode_char(encoding: Encoding, v: u8) -> u8 {
+    let v = v as i16;
+    let c = match encoding {
+        Base32 | Base32NoPad => {
+            (range_mask(v, 0, 25) & (v + b'A' as i16)) |
+            (range_mask(v, 26, 31) & (v - 26 + b'2' as i16))
+        }
+        _ => {
+            let (c62, c63) = specials(encoding);
+            (range_mask(v, 0, 25) & (v + b'A' as i16)) |
+            (range_mask(v, 26, 51) & (v - 26 + b'a' as i16)) |
+            (range_mask(v, 52, 61) & (v - 52 + b'0' as i16)) |
+            (range_mask(v, 62, 62) & c62) |
+            (range_mask(v, 63, 63) & c63)
+        }
+    };
+    c as u8
+}
+
+// Return the value of character `c` and a mask set to 0xff iff `c` is
+// valid; 0 otherwise. No table nor branch.
+fn decode_char(encoding: Encoding, c: u8) -> (u8, u8) {
+    let c = c as i16;
+    let (v, valid) = match encoding {
+        Base32 | Base32NoPad => {
+            let upper = range_mask(c, b'A' as i16, b'Z' as i16);
+            let digit = range_mask(c, b'2' as i16, b'7' as i16);
+            ((upper & (c - b'A' as i16)) | (digit & (c - b'2' as i16 + 26)),
+             upper | digit)
+        }
+        _ => {
+            let (c62, c63) = specials(encoding);
+            let upper = range_mask(c, b'A' as i16, b'Z' as i16);
+            let lower = range_mask(c, b'a' as i16, b'z' as i16);
+            let digit = range_mask(c, b'0' as i16, b'9' as i16);
+            let is62 = range_mask(c, c62, c62);
+            let is63 = range_mask(c, c63, c63);
+            ((upper & (c - b'A' as i16)) | (lower & (c - b'a' as i16 + 26)) |
+             (digit & (c - b'0' as i16 + 52)) | (is62 & 62) | (is63 & 63),
+             upper | lower | digit | is62 | is63)
+        }
+    };
+    (v as u8, valid as u8)
+}
+
+/// Return the length of the encoding of `len` bytes.
+pub fn encoded_len(encoding: Encoding, len: uint) -> uint {
+    let bits = char_bits(encoding);
+    let n = (len * 8 + bits - 1) / bits;
+    if is_padded(encoding) {
+        let block = block_len(encoding);
+        (n + block - 1) / block * block
+    } else {
+        n
+    }
+}
+
+// Return the number of characters of `src` without its padding, if its
+// length and its padding are canonical.
+fn data_len(encoding: Encoding, src: &[u8]) -> Result<uint, ()> {
+    let mut n = src.len();
+    if is_padded(encoding) {
+        while n > 0 && src[n - 1] == b'=' {
+            n -= 1;
+        }
+    }
+    if encoded_len(encoding, n * char_bits(encoding) / 8) != src.len() {
+        return Err(());
+    }
+    Ok(n)
+}
+
+/// Return the length of the data encoded by `src`, or an error if the
+/// length or the padding of `src` is not canonical.
+pub fn decoded_len(encoding: Encoding, src: &[u8]) -> Result<uint, ()> {
+    let n = try!(data_len(encoding, src));
+    Ok(n * char_bits(encoding) / 8)
+}
+
+/// Encode `src` to `dst`, in constant-time. `dst` length must be
+/// `encoded_len(encoding, src.len())`.
+pub fn encode(encoding: Encoding, src: &[u8], dst: &mut [u8]) {
+    assert_eq!(dst.len(), encoded_len(encoding, src.len()));
+    let bits = char_bits(encoding);
+    let mask = (1u32 << bits) - 1;
+
+    let mut acc = 0u32;
+    let mut acc_bits = 0u;
+    let mut pos = 0u;
+    for &b in src.iter() {
+        acc = (acc << 8) | b as u32;
+        acc_bits += 8;
+        while acc_bits >= bits {
+            acc_bits -= bits;
+            dst[pos] = encode_char(encoding, ((acc >> acc_bits) & mask) as u8);
+            pos += 1;
+        }
+    }
+    if acc_bits > 0 {
+        dst[pos] = encode_char(encoding,
+                               ((acc << (bits - acc_bits)) & mask) as u8);
+        pos += 1;
+    }
+    for c in dst[mut pos..].iter_mut() {
+        *c = b'=';
+    }
+}
+
+/// Decode `src` to `dst`, in constant-time. `dst` length must be
+/// `decoded_len(encoding, src)`. Every character is processed even if
+/// an invalid one is met, an error is returned if `src` is not the
+/// canonical encoding of some data, `dst` is then zeroed-out.
+pub fn decode(encoding: Encoding, src: &[u8],
+              dst: &mut [u8]) -> Result<(), ()> {
+    let n = try!(data_len(encoding, src));
+    let bits = char_bits(encoding);
+    assert_eq!(dst.len(), n * bits / 8);
+
+    let mut valid = 0xffu8;
+    let mut acc = 0u32;
+    let mut acc_bits = 0u;
+    let mut pos = 0u;
+    for &c in src[..n].iter() {
+        let (v, v_valid) = decode_char(encoding, c);
+        valid &= v_valid;
+        acc = (acc << bits) | v as u32;
+        acc_bits += bits;
+        if acc_bits >= 8 {
+            acc_bits -= 8;
+            dst[pos] = (acc >> acc_bits) as u8;
+            pos += 1;
+        }
+    }
+    // Unused trailing bits must be zero.
+    let trailing = (acc & ((1u32 << acc_bits) - 1)) as u8;
+    valid &= utils::byte_eq(trailing, 0) * 0xff;
+
+    if utils::Choice::new(valid & 1).to_bool() {
+        Ok(())
+    } else {
+        utils::zero_memory(dst);
+        Err(())
+    }
+}
+
+/// Encode `src` into a secure string, see `encode`.
+pub fn encode_to_sstring<A: Allocator>(encoding: Encoding,
+                                       src: &[u8]) -> SString<A> {
+    let mut buf: SBuf<A, u8> = SBuf::new_zero(encoded_len(encoding,
+                                                          src.len()));
+    encode(encoding, src, buf[mut]);
+    unsafe {
+        SString::from_utf8_unchecked(buf)
+    }
+}
+
+/// Decode `src` into a secure buffer, see `decode`.
+pub fn decode_to_sbuf<A: Allocator>(encoding: Encoding,
+                                    src: &str) -> Result<SBuf<A, u8>, ()> {
+    let src = src.as_bytes();
+    let len = try!(decoded_len(encoding, src));
+    let mut buf: SBuf<A, u8> = SBuf::new_zero(len);
+    try!(decode(encoding, src, buf[mut]));
+    Ok(buf)
+}
+
+
+#[cfg(test)]
+mod test {
+    use serialize::base64::{ToBase64, STANDARD};
+    use std::rand::random;
+
+    use sbuf::{GuardedHeapAllocator, SBuf};
+    use sstring::SString;
+    use utils::encoding;
+    use utils::encoding::{Encoding, Base64, Base64NoPad, Base64Url,
+                          Base64UrlNoPad, Base32, Base32NoPad};
+
+
+    // RFC 4648, section 10.
+    static VECTORS: [(&'static str, &'static str, &'static str), ..7] = [
+        ("", "", ""),
+        ("f", "Zg==", "MY======"),
+        ("fo", "Zm8=", "MZXQ===="),
+        ("foo", "Zm9v", "MZXW6==="),
+        ("foob", "Zm9vYg==", "MZXW6YQ="),
+        ("fooba", "Zm9vYmE=", "MZXW6YTB"),
+        ("foobar", "Zm9vYmFy", "MZXW6YTBOI======")
+    ];
+
+    fn check(encoding: Encoding, data: &[u8], encoded: &str) {
+        let s: SString<GuardedHeapAllocator> =
+            encoding::encode_to_sstring(encoding, data);
+        assert_eq!(s.as_str(), encoded);
+        let b: SBuf<GuardedHeapAllocator, u8> =
+            encoding::decode_to_sbuf(encoding, encoded).unwrap();
+        assert!(b[] == data);
+    }
+
+    fn is_rejected(encoding: Encoding, encoded: &str) -> bool {
+        let r: Result<SBuf<GuardedHeapAllocator, u8>, ()> =
+            encoding::decode_to_sbuf(encoding, encoded);
+        r.is_err()
+    }
+
+    #[test]
+    fn test_vectors() {
+        for &(data, b64, b32) in VECTORS.iter() {
+            check(Base64, data.as_bytes(), b64);
+            check(Base64NoPad, data.as_bytes(), b64.trim_right_chars('='));
+            check(Base32, data.as_bytes(), b32);
+            check(Base32NoPad, data.as_bytes(), b32.trim_right_chars('='));
+        }
+        check(Base64, [0xfbu8, 0xff], "+/8=");
+        check(Base64Url, [0xfbu8, 0xff], "-_8=");
+        check(Base64UrlNoPad, [0xfbu8, 0xff], "-_8");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let encodings = [Base64, Base64NoPad, Base64Url, Base64UrlNoPad,
+                         Base32, Base32NoPad];
+        for len in range(0u, 70) {
+            let v = Vec::from_fn(len, |_| random::<u8>());
+            check(Base64, v[], v[].to_base64(STANDARD).as_slice());
+            for &e in encodings.iter() {
+                let s: SString<GuardedHeapAllocator> =
+                    encoding::encode_to_sstring(e, v[]);
+                check(e, v[], s.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn test_strict() {
+        // Non-zero trailing bits.
+        assert!(is_rejected(Base64, "Zh=="));
+        assert!(is_rejected(Base32, "MZ======"));
+        // Bad padding.
+        assert!(is_rejected(Base64, "Zg="));
+        assert!(is_rejected(Base64, "Zg"));
+        assert!(is_rejected(Base64, "Zg==="));
+        assert!(is_rejected(Base64, "Z==="));
+        assert!(is_rejected(Base64, "===="));
+        assert!(is_rejected(Base64NoPad, "Zg=="));
+        assert!(is_rejected(Base32, "MY====="));
+        assert!(is_rejected(Base32NoPad, "MYA"));
+        // Invalid characters.
+        assert!(is_rejected(Base64, "Zm9v\n"));
+        assert!(is_rejected(Base64, "Zm 9"));
+        assert!(is_rejected(Base64, "Zm=v"));
+        assert!(is_rejected(Base64, "-_8="));
+        assert!(is_rejected(Base64Url, "+/8="));
+        assert!(is_rejected(Base32, "my======"));
+        assert!(is_rejected(Base32, "MZXW1==="));
+    }
+}
@@ -0,0 +1,1747 @@
+//! Crypto utils
+use libc::funcs::posix88::unistd;
+use libc::types::common::c95::c_void;
+use libc::types::os::arch::c95::size_t;
+use libc::types::os::arch::posix88::pid_t;
+use std::cmp;
+use std::fmt;
+use std::intrinsics;
+use std::io::{mod, File, IoError, IoResult};
+use std::mem;
+use std::num;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::rand::{Rng, SeedableRng};
+use std::rand::os::OsRng;
+use std::slice::MutableSlice;
+use std::sync::atomic;
+use std::uint;
+
+use sbuf::{Allocator, SBuf};
+use sstring::SString;
+
+pub mod drbg;
+pub mod encoding;
+mod sha256;
+
+
+macro_rules! int_codec_impl(
+    ($t:ty, $size:expr,
+     $read_le:ident, $read_be:ident, $write_le:ident, $write_be:ident,
+     $read_le_into:ident, $read_be_into:ident,
+     $write_le_from:ident, $write_be_from:ident) => (
+        /// Decode integer from the first bytes of `buf` in little-endian.
+        pub fn $read_le(buf: &[u8]) -> $t {
+            assert!(buf.len() >= $size);
+            let mut val: $t = 0;
+            for i in range(0u, $size) {
+                val |= buf[i] as $t << (8 * i);
+            }
+            val
+        }
+
+        /// Decode integer from the first bytes of `buf` in big-endian.
+        pub fn $read_be(buf: &[u8]) -> $t {
+            assert!(buf.len() >= $size);
+            let mut val: $t = 0;
+            for i in range(0u, $size) {
+                val |= buf[i] as $t << (8 * ($size - 1 - i));
+            }
+            val
+        }
+
+        /// Encode integer in little-endian to the first bytes of `buf`.
+        pub fn $write_le(buf: &mut [u8], val: $t) {
+            assert!(buf.len() >= $size);
+            for i in range(0u, $size) {
+                buf[i] = (val >> (8 * i)) as u8;
+            }
+        }
+
+        /// Encode integer in big-endian to the first bytes of `buf`.
+        pub fn $write_be(buf: &mut [u8], val: $t) {
+            assert!(buf.len() >= $size);
+            for i in range(0u, $size) {
+                buf[i] = (val >> (8 * ($size - 1 - i))) as u8;
+            }
+        }
+
+        /// Decode `dst.len()` little-endian integers from `src`.
+        pub fn $read_le_into(dst: &mut [$t], src: &[u8]) {
+            assert!(src.len() >= dst.len() * $size);
+            for (i, x) in dst.iter_mut().enumerate() {
+                *x = $read_le(src[i * $size..]);
+            }
+        }
+
+        /// Decode `dst.len()` big-endian integers from `src`.
+        pub fn $read_be_into(dst: &mut [$t], src: &[u8]) {
+            assert!(src.len() >= dst.len() * $size);
+            for (i, x) in dst.iter_mut().enumerate() {
+                *x = $read_be(src[i * $size..]);
+            }
+        }
+
+        /// Encode integers of `src` in little-endian to `dst`.
+        pub fn $write_le_from(dst: &mut [u8], src: &[$t]) {
+            assert!(dst.len() >= src.len() * $size);
+            for (i, x) in src.iter().enumerate() {
+                $write_le(dst[mut i * $size..], *x);
+            }
+        }
+
+        /// Encode integers of `src` in big-endian to `dst`.
+        pub fn $write_be_from(dst: &mut [u8], src: &[$t]) {
+            assert!(dst.len() >= src.len() * $size);
+            for (i, x) in src.iter().enumerate() {
+                $write_be(dst[mut i * $size..], *x);
+            }
+        }
+    )
+)
+
+int_codec_impl!(u16, 2, read_u16_le, read_u16_be, write_u16_le, write_u16_be,
+                read_u16_le_into, read_u16_be_into,
+                write_u16_le_from, write_u16_be_from)
+
+int_codec_impl!(u32, 4, read_u32_le, read_u32_be, write_u32_le, write_u32_be,
+                read_u32_le_into, read_u32_be_into,
+                write_u32_le_from, write_u32_be_from)
+
+int_codec_impl!(u64, 8, read_u64_le, read_u64_be, write_u64_le, write_u64_be,
+                read_u64_le_into, read_u64_be_into,
+                write_u64_le_from, write_u64_be_from)
+
+/// `Bytes` to `u32` little-endian decoding, `val` is overwritten.
+pub fn u8to32_le(val: &mut u32, buf: &[u8]) {
+    *val = read_u32_le(buf);
+}
+
+/// `u32` to `bytes` little-endian encoding.
+pub fn u32to8_le(buf: &mut [u8], val: &u32) {
+    write_u32_le(buf, *val);
+}
+
+/// `Bytes` to `u64` little-endian decoding, `val` is overwritten.
+pub fn u8to64_le(val: &mut u64, buf: &[u8]) {
+    *val = read_u64_le(buf);
+}
+
+/// `u64` to `bytes` little-endian encoding.
+pub fn u64to8_le(buf: &mut [u8], val: &u64) {
+    write_u64_le(buf, *val);
+}
+
+
+/// Pad to multiple of 16.
+///
+/// Return `n` padding bytes to make `len + n` the shortest multiple of 16.
+/// `n` is comprised between `0` and `15`.
+pub fn pad16(len: uint) -> Vec<u8> {
+    pad(ZeroPadding, len, 16)
+}
+
+/// Block padding schemes.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Padding {
+    /// PKCS#7, `n` bytes of value `n`.
+    Pkcs7Padding,
+    /// ISO/IEC 7816-4, byte `0x80` followed by zero bytes.
+    Iso7816Padding,
+    /// ANSI X.923, zero bytes followed by a last byte of value `n`.
+    AnsiX923Padding,
+    /// ISO 10126, random bytes followed by a last byte of value `n`.
+    Iso10126Padding,
+    /// Zero bytes, no padding is added to already aligned data.
+    ZeroPadding
+}
+
+/// Pad to multiple of `block_size`.
+///
+/// Return the `n` padding bytes to append to data of length `len`
+/// according to scheme `padding`. `n` is comprised between `1` and
+/// `block_size` except for `ZeroPadding` where it is comprised between
+/// `0` and `block_size - 1`. `block_size` must be lower than 256. Fails
+/// if the system RNG failed, see `try_pad`.
+pub fn pad(padding: Padding, len: uint, block_size: uint) -> Vec<u8> {
+    match try_pad(padding, len, block_size) {
+        Ok(v) => v,
+        Err(err) => panic!("{}", err)
+    }
+}
+
+/// Like `pad` but return an error if the system RNG failed, only
+/// `Iso10126Padding` draws random bytes from `SysRng`.
+pub fn try_pad(padding: Padding, len: uint,
+               block_size: uint) -> IoResult<Vec<u8>> {
+    assert!(block_size > 0 && block_size < 256);
+    let n = block_size - (len % block_size);
+
+    let v = match padding {
+        Pkcs7Padding => Vec::from_elem(n, n as u8),
+        Iso7816Padding => {
+            let mut v = Vec::from_elem(n, 0u8);
+            v[mut][0] = 0x80;
+            v
+        }
+        AnsiX923Padding => {
+            let mut v = Vec::from_elem(n, 0u8);
+            v[mut][n - 1] = n as u8;
+            v
+        }
+        Iso10126Padding => {
+            let mut v = Vec::from_elem(n, 0u8);
+            try!(SysRng::new().try_fill_bytes(v[mut]));
+            v[mut][n - 1] = n as u8;
+            v
+        }
+        ZeroPadding => Vec::from_elem(n % block_size, 0u8)
+    };
+    Ok(v)
+}
+
+// Return 1 iff x == y; 0 otherwise.
+fn uint_eq(x: uint, y: uint) -> uint {
+    let z = x ^ y;
+    1 ^ ((z | (0 - z)) >> (uint::BITS - 1))
+}
+
+// Return 1 iff x < y; 0 otherwise. Both values must be lower than
+// 2^(uint::BITS - 1).
+fn uint_lt(x: uint, y: uint) -> uint {
+    (x - y) >> (uint::BITS - 1)
+}
+
+/// Remove padding.
+///
+/// Return the length of `buf` once its padding applied with scheme
+/// `padding` is removed. `buf` length must be a non null multiple of
+/// `block_size` (except for `ZeroPadding` where it can be empty). The
+/// whole last block is always processed and the bytes of `buf` are never
+/// branched on, the same error is returned whatever the reason of the
+/// failure.
+pub fn unpad(padding: Padding, buf: &[u8],
+             block_size: uint) -> Result<uint, ()> {
+    assert!(block_size > 0 && block_size < 256);
+    let len = buf.len();
+    if len % block_size != 0 {
+        return Err(());
+    }
+    if len == 0 {
+        return match padding {
+            ZeroPadding => Ok(0),
+            _ => Err(())
+        };
+    }
+
+    let last = buf[len - 1] as uint;
+    let mut good: uint = 1;
+    let mut n: uint = 0;
+
+    match padding {
+        Pkcs7Padding | AnsiX923Padding | Iso10126Padding => {
+            n = last;
+            good &= uint_lt(0, n) & uint_lt(n, block_size + 1);
+
+            for i in range(1u, block_size) {
+                let b = buf[len - 1 - i] as uint;
+                let in_pad = uint_lt(i, n);
+                good &= match padding {
+                    Pkcs7Padding => (1 ^ in_pad) | uint_eq(b, n),
+                    AnsiX923Padding => (1 ^ in_pad) | uint_eq(b, 0),
+                    _ => 1
+                };
+            }
+        }
+        Iso7816Padding | ZeroPadding => {
+            // Look for the last non zero byte, it must be 0x80 with
+            // ISO/IEC 7816-4.
+            let mut found: uint = 0;
+            let mut marker: uint = 0;
+            for i in range(0u, block_size) {
+                let b = buf[len - 1 - i] as uint;
+                let hit = (1 ^ found) & (1 ^ uint_eq(b, 0));
+                marker |= hit & uint_eq(b, 0x80);
+                n += (1 ^ found) & (1 ^ hit);
+                found |= hit;
+            }
+            match padding {
+                Iso7816Padding => {
+                    good &= marker;
+                    n += 1;
+                }
+                _ => ()
+            }
+        }
+    }
+
+    if good == 1 {
+        Ok(len - n)
+    } else {
+        Err(())
+    }
+}
+
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod impzero {
+    use libc::types::common::c95::c_void;
+    use libc::types::os::arch::c95::{c_char, size_t};
+    use std::mem;
+    use std::sync::{Once, ONCE_INIT};
+
+
+    extern {
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    static INIT: Once = ONCE_INIT;
+    static mut EXPLICIT_BZERO: *mut c_void = 0 as *mut c_void;
+
+    // explicit_bzero is only provided by glibc >= 2.25 and musl >= 1.1.20,
+    // it is looked up at runtime to keep working with older versions.
+    pub unsafe fn explicit_bzero() -> Option<extern "C" fn(*mut c_void,
+                                                           size_t)> {
+        INIT.doit(|| {
+            // A null handle stands for RTLD_DEFAULT.
+            EXPLICIT_BZERO = dlsym(0 as *mut c_void,
+                                   b"explicit_bzero\0".as_ptr() as *const c_char);
+        });
+        if EXPLICIT_BZERO.is_null() {
+            None
+        } else {
+            Some(mem::transmute(EXPLICIT_BZERO))
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod impzero {
+    use libc::types::common::c95::c_void;
+    use libc::types::os::arch::c95::size_t;
+
+
+    pub unsafe fn explicit_bzero() -> Option<extern "C" fn(*mut c_void,
+                                                           size_t)> {
+        None
+    }
+}
+
+/// Zero-out `count` elements starting at `ptr`.
+///
+/// Use `explicit_bzero` when the libc provides it, otherwise fall back
+/// to volatile writes. In both cases the writes are followed by a
+/// memory fence so that they can't be elided nor reordered after the
+/// release of the memory.
+pub unsafe fn zero_raw_memory<T>(ptr: *mut T, count: uint) {
+    match impzero::explicit_bzero() {
+        Some(bzero) => {
+            bzero(ptr as *mut c_void,
+                  (count * mem::size_of::<T>()) as size_t)
+        }
+        None => intrinsics::volatile_set_memory(ptr, 0, count)
+    }
+    atomic::fence(atomic::SeqCst);
+}
+
+/// Zero-out memory buffer.
+pub fn zero_memory<T>(b: &mut [T]) {
+    unsafe {
+        zero_raw_memory(b.as_mut_ptr(), b.len());
+    }
+}
+
+/// Trait for values whose memory can be zeroed-out.
+pub trait Zeroize {
+    /// Zero-out `self` in place.
+    fn zeroize(&mut self);
+}
+
+macro_rules! zeroize_int_impl(
+    ($($t:ty),*) => ($(
+        impl Zeroize for $t {
+            fn zeroize(&mut self) {
+                unsafe {
+                    intrinsics::volatile_store(self as *mut $t, 0);
+                }
+            }
+        }
+    )*)
+)
+
+zeroize_int_impl!(u8, u16, u32, u64, uint, i8, i16, i32, i64, int)
+
+macro_rules! zeroize_array_impl(
+    ($($n:expr),*) => ($(
+        impl<T: Zeroize> Zeroize for [T, ..$n] {
+            fn zeroize(&mut self) {
+                for x in self.iter_mut() {
+                    x.zeroize();
+                }
+            }
+        }
+    )*)
+)
+
+zeroize_array_impl!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+                    17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30,
+                    31, 32, 48, 64, 128, 256)
+
+impl<T: Zeroize> Zeroize for Vec<T> {
+    /// Zero-out all the elements and the spare capacity, `self` is left
+    /// empty.
+    fn zeroize(&mut self) {
+        for x in self.iter_mut() {
+            x.zeroize();
+        }
+        self.truncate(0);
+        unsafe {
+            zero_raw_memory(self.as_mut_ptr(), self.capacity());
+        }
+    }
+}
+
+impl Zeroize for String {
+    /// Zero-out its bytes and the spare capacity, `self` is left empty.
+    fn zeroize(&mut self) {
+        unsafe {
+            self.as_mut_vec().zeroize();
+        }
+    }
+}
+
+impl<T: Zeroize> Zeroize for Option<T> {
+    /// Zero-out the contained value if any, `self` is left to `None`.
+    fn zeroize(&mut self) {
+        match *self {
+            Some(ref mut v) => v.zeroize(),
+            None => ()
+        }
+        *self = None;
+    }
+}
+
+/// Wrapper zeroing-out its value when it is dropped.
+pub struct Zeroizing<T> {
+    value: T
+}
+
+impl<T: Zeroize> Zeroizing<T> {
+    /// Wrap `value`.
+    pub fn new(value: T) -> Zeroizing<T> {
+        Zeroizing {
+            value: value
+        }
+    }
+}
+
+impl<T: Zeroize> Deref<T> for Zeroizing<T> {
+    fn deref<'a>(&'a self) -> &'a T {
+        &self.value
+    }
+}
+
+impl<T: Zeroize> DerefMut<T> for Zeroizing<T> {
+    fn deref_mut<'a>(&'a mut self) -> &'a mut T {
+        &mut self.value
+    }
+}
+
+#[unsafe_destructor]
+impl<T: Zeroize> Drop for Zeroizing<T> {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+/// Copy memory buffer.
+///
+/// Copy `count` elements from slice `src` to mutable slice `dst`.
+/// Requirement: `count >= min(srclen, dstlen)`.
+pub fn copy_slice_memory<T>(dst: &mut[T], src: &[T], count: uint) {
+    assert!(dst.len() >= count && src.len() >= count);
+    unsafe {
+        ptr::copy_nonoverlapping_memory(dst.as_mut_ptr(),
+                                        src.as_ptr(),
+                                        count);
+    }
+}
+
+
+#[cfg(all(feature = "ctgrind", target_arch = "x86_64"))]
+mod impvalgrind {
+    // Memcheck client requests, see valgrind/memcheck.h.
+    static MAKE_MEM_UNDEFINED: u64 = 0x4d430001;
+    static MAKE_MEM_DEFINED: u64 = 0x4d430002;
+
+    // Issue a Valgrind client request, see valgrind/valgrind.h. The
+    // special instructions sequence is a no-op when not running under
+    // Valgrind.
+    unsafe fn client_request(request: u64, addr: u64, len: u64) {
+        let args: [u64, ..6] = [request, addr, len, 0, 0, 0];
+        let _result: u64;
+        asm!("rolq $$3, %rdi\n\t\
+              rolq $$13, %rdi\n\t\
+              rolq $$61, %rdi\n\t\
+              rolq $$51, %rdi\n\t\
+              xchgq %rbx, %rbx"
+             : "={rdx}"(_result)
+             : "{rax}"(args.as_ptr()), "{rdx}"(0u64)
+             : "cc", "memory"
+             : "volatile");
+    }
+
+    pub unsafe fn make_mem_undefined(ptr: *const u8, len: uint) {
+        client_request(MAKE_MEM_UNDEFINED, ptr as u64, len as u64)
+    }
+
+    pub unsafe fn make_mem_defined(ptr: *const u8, len: uint) {
+        client_request(MAKE_MEM_DEFINED, ptr as u64, len as u64)
+    }
+}
+
+// Client requests are only implemented on x86_64, fail to build rather
+// than silently checking nothing on other architectures.
+#[cfg(all(feature = "ctgrind", not(target_arch = "x86_64")))]
+ctgrind_feature_is_only_supported_on_x86_64!()
+
+#[cfg(not(feature = "ctgrind"))]
+mod impvalgrind {
+    pub unsafe fn make_mem_undefined(_: *const u8, _: uint) {
+    }
+
+    pub unsafe fn make_mem_defined(_: *const u8, _: uint) {
+    }
+}
+
+/// Mark `buf` as secret.
+///
+/// With the `ctgrind` feature, `buf` is marked as undefined for
+/// Valgrind's memcheck so that any branch or memory index depending on
+/// its content is reported when running under memcheck (ctgrind
+/// technique). Without this feature it does nothing.
+pub fn classify<T>(buf: &[T]) {
+    unsafe {
+        impvalgrind::make_mem_undefined(buf.as_ptr() as *const u8,
+                                        buf.len() * mem::size_of::<T>());
+    }
+}
+
+/// Mark `buf` as public, its content can be branched on from now on.
+/// See `classify`.
+pub fn declassify<T>(buf: &[T]) {
+    unsafe {
+        impvalgrind::make_mem_defined(buf.as_ptr() as *const u8,
+                                      buf.len() * mem::size_of::<T>());
+    }
+}
+
+// Return 1 iff x == y; 0 otherwise.
+fn byte_eq(x: u8, y: u8) -> u8 {
+    let mut z: u8 = !(x ^ y);
+    z &= z >> 4;
+    z &= z >> 2;
+    z &= z >> 1;
+    z
+}
+
+// Opaque identity function, prevent the optimizer from inferring that
+// a value is a boolean and from turning its uses into branches.
+#[inline(never)]
+fn black_box(x: u8) -> u8 {
+    unsafe {
+        intrinsics::volatile_load(&x as *const u8)
+    }
+}
+
+/// Constant-time boolean, holds `0` or `1`.
+///
+/// Combine choices with `&`, `|`, `^` and `!` without branching, only
+/// convert them to `bool` with `to_bool` once the result is not secret
+/// anymore.
+#[deriving(Clone)]
+pub struct Choice(u8);
+
+impl Choice {
+    /// New choice from `v`, only its lowest bit is considered.
+    pub fn new(v: u8) -> Choice {
+        Choice(black_box(v & 1))
+    }
+
+    /// Return the value of the choice, `0` or `1`.
+    pub fn unwrap_u8(&self) -> u8 {
+        let Choice(v) = *self;
+        v
+    }
+
+    /// Convert to `bool`, this conversion is not constant-time and the
+    /// value is declassified (see `classify`).
+    pub fn to_bool(&self) -> bool {
+        let v = [self.unwrap_u8()];
+        declassify(v[]);
+        unsafe {
+            intrinsics::volatile_load(&v[0] as *const u8) == 1
+        }
+    }
+}
+
+// The value is secret, it is never formatted.
+impl fmt::Show for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Choice(*)")
+    }
+}
+
+impl BitAnd<Choice, Choice> for Choice {
+    fn bitand(&self, rhs: &Choice) -> Choice {
+        Choice::new(self.unwrap_u8() & rhs.unwrap_u8())
+    }
+}
+
+impl BitOr<Choice, Choice> for Choice {
+    fn bitor(&self, rhs: &Choice) -> Choice {
+        Choice::new(self.unwrap_u8() | rhs.unwrap_u8())
+    }
+}
+
+impl BitXor<Choice, Choice> for Choice {
+    fn bitxor(&self, rhs: &Choice) -> Choice {
+        Choice::new(self.unwrap_u8() ^ rhs.unwrap_u8())
+    }
+}
+
+impl Not<Choice> for Choice {
+    fn not(&self) -> Choice {
+        Choice::new(1 ^ self.unwrap_u8())
+    }
+}
+
+/// Constant-time optional value, `value` is only meaningful when
+/// `is_some` is `1`.
+#[deriving(Clone)]
+pub struct CtOption<T> {
+    value: T,
+    is_some: Choice
+}
+
+impl<T> CtOption<T> {
+    /// New optional value, `value` must be set even if `is_some` is `0`.
+    pub fn new(value: T, is_some: Choice) -> CtOption<T> {
+        CtOption {
+            value: value,
+            is_some: is_some
+        }
+    }
+
+    /// Return a choice set to `1` iff a value is present.
+    pub fn is_some(&self) -> Choice {
+        self.is_some
+    }
+
+    /// Return a choice set to `1` iff no value is present.
+    pub fn is_none(&self) -> Choice {
+        !self.is_some
+    }
+
+    /// Apply `f` to the value, `f` is always called even if no value is
+    /// present.
+    pub fn map<U>(self, f: |T| -> U) -> CtOption<U> {
+        CtOption::new(f(self.value), self.is_some)
+    }
+
+    /// Return the value. Fails if no value is present, this is not
+    /// constant-time.
+    pub fn unwrap(self) -> T {
+        assert!(self.is_some.to_bool(), "CtOption::unwrap on a none value");
+        self.value
+    }
+
+    /// Convert to `Option`, this conversion is not constant-time.
+    pub fn to_option(self) -> Option<T> {
+        if self.is_some.to_bool() {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: CtSelect> CtOption<T> {
+    /// Return the value if present, `default` otherwise, in
+    /// constant-time.
+    pub fn unwrap_or(self, default: T) -> T {
+        let mut r = default;
+        r.ct_assign(self.is_some, &self.value);
+        r
+    }
+}
+
+// Neither the value nor its presence are formatted.
+impl<T> fmt::Show for CtOption<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CtOption(*)")
+    }
+}
+
+/// Compare bytes buffers in constant-time.
+///
+/// Return a choice set to `1` iff `x == y`. Only the length of the
+/// buffers is compared in variable-time.
+pub fn bytes_ct_eq<T>(x: &[T], y: &[T]) -> Choice {
+    if x.len() != y.len() {
+        return Choice::new(0);
+    }
+
+    let size = x.len() * mem::size_of::<T>();
+    let px = x.as_ptr() as *const u8;
+    let py = y.as_ptr() as *const u8;
+
+    let mut d: u8 = 0;
+    unsafe {
+        for i in range(0u, size) {
+            d |= *px.offset(i as int) ^ *py.offset(i as int);
+        }
+    }
+
+    Choice::new(byte_eq(d, 0))
+}
+
+/// Compare bytes buffers.
+///
+/// Return `true` iff `x == y`; `false` otherwise. See `bytes_ct_eq` to
+/// keep the result as a `Choice`.
+pub fn bytes_eq<T>(x: &[T], y: &[T]) -> bool {
+    bytes_ct_eq(x, y).to_bool()
+}
+
+/// Constant-time ordering of unsigned integers.
+pub trait CtOrd {
+    /// Return a choice set to `1` iff `self < other`.
+    fn ct_lt(&self, other: &Self) -> Choice;
+
+    /// Return a choice set to `1` iff `self > other`.
+    fn ct_gt(&self, other: &Self) -> Choice {
+        other.ct_lt(self)
+    }
+
+    /// Return a choice set to `1` iff `self <= other`.
+    fn ct_le(&self, other: &Self) -> Choice {
+        !self.ct_gt(other)
+    }
+
+    /// Return a choice set to `1` iff `self >= other`.
+    fn ct_ge(&self, other: &Self) -> Choice {
+        !self.ct_lt(other)
+    }
+}
+
+macro_rules! ct_ord_impl(
+    ($($t:ty),*) => ($(
+        impl CtOrd for $t {
+            fn ct_lt(&self, other: &$t) -> Choice {
+                let (x, y) = (*self, *other);
+                // See Hacker's Delight 2-12.
+                let z = (!x & y) | ((!x | y) & (x - y));
+                Choice::new((z >> (mem::size_of::<$t>() * 8 - 1)) as u8)
+            }
+        }
+    )*)
+)
+
+ct_ord_impl!(u8, u16, u32, u64, uint)
+
+/// Return a choice set to `1` iff `x < y`, in constant-time.
+pub fn ct_lt<T: CtOrd>(x: T, y: T) -> Choice {
+    x.ct_lt(&y)
+}
+
+/// Return a choice set to `1` iff `x > y`, in constant-time.
+pub fn ct_gt<T: CtOrd>(x: T, y: T) -> Choice {
+    x.ct_gt(&y)
+}
+
+/// Return a choice set to `1` iff `x <= y`, in constant-time.
+pub fn ct_le<T: CtOrd>(x: T, y: T) -> Choice {
+    x.ct_le(&y)
+}
+
+// Compare bytes of x and y in the order given by indexes, which must be
+// valid in both, the first different bytes decide of the result.
+fn bytes_cmp_iter<I: Iterator<uint>>(x: &[u8], y: &[u8], indexes: I) -> i8 {
+    let mut gt = Choice::new(0);
+    let mut lt = Choice::new(0);
+    for i in indexes {
+        let undecided = !(gt | lt);
+        gt = gt | (undecided & x[i].ct_gt(&y[i]));
+        lt = lt | (undecided & x[i].ct_lt(&y[i]));
+    }
+    gt.unwrap_u8() as i8 - lt.unwrap_u8() as i8
+}
+
+/// Compare big-endian numbers or byte strings in constant-time.
+///
+/// Return `-1`, `0` or `1` iff respectively `x < y`, `x == y` or
+/// `x > y` in lexicographic order, which is also the numeric order of
+/// buffers of the same length. Every byte of the common prefix of `x`
+/// and `y` is always processed, if it is equal the shortest buffer is
+/// the lowest, lengths are not secret.
+pub fn bytes_cmp_be(x: &[u8], y: &[u8]) -> i8 {
+    let n = cmp::min(x.len(), y.len());
+    let r = bytes_cmp_iter(x, y, range(0u, n));
+    let by_len = (x.len() > y.len()) as i8 - (x.len() < y.len()) as i8;
+    // Lengths only decide when the common prefix is equal.
+    let tie = 0 - byte_eq(r as u8, 0) as i8;
+    r | (tie & by_len)
+}
+
+/// Compare little-endian numbers in constant-time.
+///
+/// Like `bytes_cmp_be` for buffers of the same length but the bytes are
+/// processed from the last one which is the most significant. Buffers
+/// must have the same length.
+pub fn bytes_cmp_le(x: &[u8], y: &[u8]) -> i8 {
+    assert_eq!(x.len(), y.len());
+    bytes_cmp_iter(x, y, range(0u, x.len()).rev())
+}
+
+/// Constant-time conditional assignment and swap.
+///
+/// Like the free functions of this module and `SBuf`, the choice is
+/// always the first argument.
+pub trait CtSelect {
+    /// Assign `other` to `self` iff `choice` is `1`, leave `self`
+    /// unchanged iff `choice` is `0`.
+    fn ct_assign(&mut self, choice: Choice, other: &Self);
+
+    /// Swap `self` and `other` iff `choice` is `1`, leave them unchanged
+    /// iff `choice` is `0`.
+    fn ct_swap(&mut self, choice: Choice, other: &mut Self);
+}
+
+macro_rules! ct_select_impl(
+    ($($t:ty),*) => ($(
+        impl CtSelect for $t {
+            fn ct_assign(&mut self, choice: Choice, other: &$t) {
+                let mask = (0 as $t) - (choice.unwrap_u8() as $t);
+                *self ^= mask & (*self ^ *other);
+            }
+
+            fn ct_swap(&mut self, choice: Choice, other: &mut $t) {
+                let mask = (0 as $t) - (choice.unwrap_u8() as $t);
+                let t = mask & (*self ^ *other);
+                *self ^= t;
+                *other ^= t;
+            }
+        }
+    )*)
+)
+
+ct_select_impl!(u8, u16, u32, u64, uint, i8, i16, i32, i64, int)
+
+macro_rules! ct_select_array_impl(
+    ($($n:expr),*) => ($(
+        impl<T: CtSelect> CtSelect for [T, ..$n] {
+            fn ct_assign(&mut self, choice: Choice, other: &[T, ..$n]) {
+                ct_assign_slice(choice, self[mut], other[]);
+            }
+
+            fn ct_swap(&mut self, choice: Choice, other: &mut [T, ..$n]) {
+                ct_swap_slice(choice, self[mut], other[mut]);
+            }
+        }
+    )*)
+)
+
+ct_select_array_impl!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+                      17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30,
+                      31, 32, 48, 64, 128, 256)
+
+/// Return `a` iff `choice` is `1`, `b` iff `choice` is `0`, in
+/// constant-time.
+pub fn ct_select<T: CtSelect + Clone>(choice: Choice, a: &T, b: &T) -> T {
+    let mut r = b.clone();
+    r.ct_assign(choice, a);
+    r
+}
+
+/// Assign `src` to `dst` iff `choice` is `1`, in constant-time.
+pub fn ct_assign<T: CtSelect>(choice: Choice, dst: &mut T, src: &T) {
+    dst.ct_assign(choice, src)
+}
+
+/// Swap `x` and `y` iff `choice` is `1`, in constant-time.
+pub fn ct_swap<T: CtSelect>(choice: Choice, x: &mut T, y: &mut T) {
+    x.ct_swap(choice, y)
+}
+
+/// Copy `a` to `dst` iff `choice` is `1`, `b` iff `choice` is `0`, in
+/// constant-time. Slices must have the same length.
+pub fn ct_select_slice<T: CtSelect + Clone>(choice: Choice, dst: &mut [T],
+                                            a: &[T], b: &[T]) {
+    assert!(dst.len() == a.len() && a.len() == b.len());
+    for i in range(0u, dst.len()) {
+        dst[i] = ct_select(choice, &a[i], &b[i]);
+    }
+}
+
+/// Assign `src` to `dst` iff `choice` is `1`, in constant-time. Slices
+/// must have the same length.
+pub fn ct_assign_slice<T: CtSelect>(choice: Choice, dst: &mut [T],
+                                    src: &[T]) {
+    assert_eq!(dst.len(), src.len());
+    for (d, s) in dst.iter_mut().zip(src.iter()) {
+        d.ct_assign(choice, s);
+    }
+}
+
+/// Swap `x` and `y` iff `choice` is `1`, in constant-time. Slices must
+/// have the same length.
+pub fn ct_swap_slice<T: CtSelect>(choice: Choice, x: &mut [T], y: &mut [T]) {
+    assert_eq!(x.len(), y.len());
+    for (a, b) in x.iter_mut().zip(y.iter_mut()) {
+        a.ct_swap(choice, b);
+    }
+}
+
+/// Constant-time table lookup.
+///
+/// Assign `table[index]` to `out`. Every entry of `table` is read and
+/// conditionally assigned, hence neither the control flow nor the memory
+/// accesses depend on `index`. `out` is left unchanged if `index` is out
+/// of bounds. Entries are typically arrays, e.g. `table: &[[i64, ..16]]`.
+pub fn ct_lookup<T: CtSelect>(table: &[T], index: uint, out: &mut T) {
+    for (i, entry) in table.iter().enumerate() {
+        out.ct_assign(Choice::new(uint_eq(i, index) as u8), entry);
+    }
+}
+
+/// Conditionally swap bytes.
+///
+/// `x` and `y` are swapped iff `cond` is equal to `1`, there are left
+/// unchanged iff `cond` is equal to `0`. Currently only works for arrays
+/// of signed integers. `cond` is expected to be `0` or `1`.
+pub fn bytes_cswap<T: Signed + Primitive + Int>(cond: T,
+                                                x: &mut [T],
+                                                y: &mut [T]) {
+    assert_eq!(x.len(), y.len());
+
+    let c: T = !(cond - num::one());
+    for i in range(0u, x.len()) {
+        let t = c & (x[i] ^ y[i]);
+        x[i] = x[i] ^ t;
+        y[i] = y[i] ^ t;
+    }
+}
+
+
+// Return the lowercase hex digit of nibble `n` without table nor branch.
+fn hex_digit(n: u8) -> u8 {
+    // 0xff iff n > 9.
+    let above9 = ((9 - n as i16) >> 15) as u8;
+    n + b'0' + (above9 & (b'a' - b'0' - 10))
+}
+
+// Return the value of hex digit `c` (either case) and a mask set to 0xff
+// iff `c` is a valid hex digit; 0 otherwise. No table nor branch.
+fn hex_value(c: u8) -> (u8, u8) {
+    let d = c as i16 - b'0' as i16;
+    let is_digit = !((d | (9 - d)) >> 15);
+    let l = (c | 0x20) as i16 - b'a' as i16;
+    let is_alpha = !((l | (5 - l)) >> 15);
+    (((is_digit & d) | (is_alpha & (l + 10))) as u8,
+     (is_digit | is_alpha) as u8)
+}
+
+/// Hex encode `src` to `dst` with lowercase digits, in constant-time.
+/// `dst` length must be `2 * src.len()`.
+pub fn hex_encode(src: &[u8], dst: &mut [u8]) {
+    assert_eq!(dst.len(), 2 * src.len());
+    for (i, &b) in src.iter().enumerate() {
+        dst[2 * i] = hex_digit(b >> 4);
+        dst[2 * i + 1] = hex_digit(b & 0x0f);
+    }
+}
+
+/// Hex decode `src` to `dst`, in constant-time. Digits may be lowercase
+/// or uppercase, `dst` length must be `src.len() / 2`. Every digit is
+/// processed even if an invalid one is met, an error is returned if
+/// `src` length is odd or if it contains any invalid digit, `dst` is
+/// then zeroed-out.
+pub fn hex_decode(src: &[u8], dst: &mut [u8]) -> Result<(), ()> {
+    if src.len() % 2 != 0 {
+        return Err(());
+    }
+    assert_eq!(dst.len(), src.len() / 2);
+
+    let mut valid = 0xffu8;
+    for (i, x) in dst.iter_mut().enumerate() {
+        let (hi, hi_valid) = hex_value(src[2 * i]);
+        let (lo, lo_valid) = hex_value(src[2 * i + 1]);
+        *x = (hi << 4) | lo;
+        valid &= hi_valid & lo_valid;
+    }
+
+    if Choice::new(valid & 1).to_bool() {
+        Ok(())
+    } else {
+        zero_memory(dst);
+        Err(())
+    }
+}
+
+/// Hex encode `src` into a secure string, see `hex_encode`.
+pub fn to_hex<A: Allocator>(src: &[u8]) -> SString<A> {
+    let mut buf: SBuf<A, u8> = SBuf::new_zero(2 * src.len());
+    hex_encode(src, buf[mut]);
+    unsafe {
+        SString::from_utf8_unchecked(buf)
+    }
+}
+
+/// Hex decode `src` into a secure buffer, see `hex_decode`.
+pub fn from_hex<A: Allocator>(src: &str) -> Result<SBuf<A, u8>, ()> {
+    let src = src.as_bytes();
+    if src.len() % 2 != 0 {
+        return Err(());
+    }
+    let mut buf: SBuf<A, u8> = SBuf::new_zero(src.len() / 2);
+    try!(hex_decode(src, buf[mut]));
+    Ok(buf)
+}
+
+
+/// Instanciate a secure RNG based on `urandom`. Fails if `/dev/urandom`
+/// can't be opened, `SysRng` returns errors instead.
+pub fn urandom_rng() -> OsRng {
+    OsRng::new().unwrap()
+}
+
+
+static CHACHA_ROUNDS: uint = 20;
+
+/// Deterministic RNG based on the ChaCha20 keystream.
+///
+/// The 32-byte seed is used as key and the 64-bit block counter starts
+/// at `0` with a null nonce, hence the same seed always produces the same
+/// sequence. It is meant to make tests and fuzzing reproducible, use
+/// `SysRng` to generate secrets. Its state is zeroed-out on drop.
+pub struct ChaChaRng {
+    state: [u32, ..16],
+    output: [u32, ..16],
+    index: uint
+}
+
+macro_rules! chacha_quarter_round(
+    ($x:expr, $a:expr, $b:expr, $c:expr, $d:expr) => ({
+        $x[$a] += $x[$b]; $x[$d] = rotl32($x[$d] ^ $x[$a], 16);
+        $x[$c] += $x[$d]; $x[$b] = rotl32($x[$b] ^ $x[$c], 12);
+        $x[$a] += $x[$b]; $x[$d] = rotl32($x[$d] ^ $x[$a], 8);
+        $x[$c] += $x[$d]; $x[$b] = rotl32($x[$b] ^ $x[$c], 7);
+    })
+)
+
+fn rotl32(x: u32, n: uint) -> u32 {
+    (x << n) | (x >> (32 - n))
+}
+
+impl ChaChaRng {
+    // Compute the next keystream block and increment the counter.
+    fn update(&mut self) {
+        let mut x = self.state;
+        for _ in range(0u, CHACHA_ROUNDS / 2) {
+            chacha_quarter_round!(x, 0, 4, 8, 12);
+            chacha_quarter_round!(x, 1, 5, 9, 13);
+            chacha_quarter_round!(x, 2, 6, 10, 14);
+            chacha_quarter_round!(x, 3, 7, 11, 15);
+            chacha_quarter_round!(x, 0, 5, 10, 15);
+            chacha_quarter_round!(x, 1, 6, 11, 12);
+            chacha_quarter_round!(x, 2, 7, 8, 13);
+            chacha_quarter_round!(x, 3, 4, 9, 14);
+        }
+        for i in range(0u, 16) {
+            self.output[i] = x[i] + self.state[i];
+        }
+        zero_memory(x[mut]);
+
+        self.state[12] += 1;
+        if self.state[12] == 0 {
+            self.state[13] += 1;
+        }
+        self.index = 0;
+    }
+}
+
+impl Rng for ChaChaRng {
+    fn next_u32(&mut self) -> u32 {
+        if self.index == 16 {
+            self.update();
+        }
+        let v = self.output[self.index];
+        self.index += 1;
+        v
+    }
+}
+
+impl<'a> SeedableRng<&'a [u8]> for ChaChaRng {
+    /// Reset the state with key `seed`, seeds shorter than 32 bytes are
+    /// padded with zeroes.
+    fn reseed(&mut self, seed: &'a [u8]) {
+        assert!(seed.len() <= 32);
+        let mut key = [0u8, ..32];
+        copy_slice_memory(key[mut], seed, seed.len());
+
+        self.state[0] = 0x61707865;
+        self.state[1] = 0x3320646e;
+        self.state[2] = 0x79622d32;
+        self.state[3] = 0x6b206574;
+        read_u32_le_into(self.state[mut 4..12], key[]);
+        for i in range(12u, 16) {
+            self.state[i] = 0;
+        }
+        zero_memory(key[mut]);
+        self.index = 16;
+    }
+
+    /// New instance seeded with `seed`, see `reseed`.
+    fn from_seed(seed: &'a [u8]) -> ChaChaRng {
+        let mut rng = ChaChaRng {
+            state: [0, ..16],
+            output: [0, ..16],
+            index: 16
+        };
+        rng.reseed(seed);
+        rng
+    }
+}
+
+impl Drop for ChaChaRng {
+    fn drop(&mut self) {
+        zero_memory(self.state[mut]);
+        zero_memory(self.output[mut]);
+    }
+}
+
+
+// getrandom(2) is only called on the architectures whose syscall number
+// is known, others fall back to /dev/urandom.
+#[cfg(all(any(target_os = "linux", target_os = "android"),
+          any(target_arch = "x86_64", target_arch = "x86",
+              target_arch = "arm", target_arch = "aarch64")))]
+mod impgetrandom {
+    use libc::consts::os::posix88::{EINTR, ENOSYS};
+    use libc::types::common::c95::c_void;
+    use libc::types::os::arch::c95::{c_int, c_long, size_t};
+    use std::io::{IoError, IoResult};
+    use std::os;
+
+
+    extern {
+        fn syscall(number: c_long, ...) -> c_long;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    static NR_GETRANDOM: c_long = 318;
+    #[cfg(target_arch = "x86")]
+    static NR_GETRANDOM: c_long = 355;
+    #[cfg(target_arch = "arm")]
+    static NR_GETRANDOM: c_long = 384;
+    #[cfg(target_arch = "aarch64")]
+    static NR_GETRANDOM: c_long = 278;
+
+    // Fill buf with getrandom(2) without flags, thus blocking until the
+    // kernel entropy pool is initialized. Return None if the syscall is
+    // not available (Linux < 3.17).
+    pub fn getrandom(buf: &mut [u8]) -> Option<IoResult<()>> {
+        let mut read = 0u;
+        while read < buf.len() {
+            let ret = unsafe {
+                syscall(NR_GETRANDOM,
+                        buf.as_mut_ptr().offset(read as int) as *mut c_void,
+                        (buf.len() - read) as size_t,
+                        0 as c_int)
+            };
+            if ret < 0 {
+                let errno = os::errno();
+                if errno == ENOSYS as int {
+                    return None;
+                }
+                if errno != EINTR as int {
+                    return Some(Err(IoError::from_errno(errno as uint, false)));
+                }
+            } else {
+                read += ret as uint;
+            }
+        }
+        Some(Ok(()))
+    }
+}
+
+#[cfg(not(all(any(target_os = "linux", target_os = "android"),
+              any(target_arch = "x86_64", target_arch = "x86",
+                  target_arch = "arm", target_arch = "aarch64"))))]
+mod impgetrandom {
+    use std::io::IoResult;
+
+
+    pub fn getrandom(_: &mut [u8]) -> Option<IoResult<()>> {
+        None
+    }
+}
+
+/// System RNG.
+///
+/// Use the `getrandom(2)` syscall when available, it blocks until the
+/// kernel entropy pool is initialized and doesn't need any file
+/// descriptor, otherwise fall back to reading `/dev/urandom`. Errors are
+/// returned by `try_fill_bytes`, the `Rng` methods fail on error.
+pub struct SysRng {
+    getrandom: bool,
+    urandom: Option<File>
+}
+
+impl SysRng {
+    /// New instance, no resource is acquired until it is used.
+    pub fn new() -> SysRng {
+        SysRng {
+            getrandom: true,
+            urandom: None
+        }
+    }
+
+    /// Fill `dest` with random bytes.
+    pub fn try_fill_bytes(&mut self, dest: &mut [u8]) -> IoResult<()> {
+        if self.getrandom {
+            match impgetrandom::getrandom(dest) {
+                Some(res) => return res,
+                None => self.getrandom = false
+            }
+        }
+
+        if self.urandom.is_none() {
+            self.urandom = Some(try!(File::open(&Path::new("/dev/urandom"))));
+        }
+        let urandom = self.urandom.as_mut().unwrap();
+        try!(urandom.read_at_least(dest.len(), dest));
+        Ok(())
+    }
+}
+
+impl Rng for SysRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0u8, ..4];
+        self.fill_bytes(buf);
+        read_u32_le(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self.try_fill_bytes(dest) {
+            Ok(()) => (),
+            Err(err) => panic!("SysRng failed: {}", err)
+        }
+    }
+}
+
+
+static FKE_KEY_SIZE: uint = 32;
+static FKE_BUF_SIZE: uint = 512;
+
+/// Buffered fast-key-erasure RNG.
+///
+/// A 32-byte key drawn from `SysRng` is expanded with ChaCha20, the
+/// first 32 output bytes replace the key and the following ones are
+/// buffered and handed out, each served byte is erased from the buffer.
+/// Hence a compromise of the state does not reveal past outputs. The
+/// process id is checked on each request and a new key is drawn from
+/// `SysRng` after a `fork()`, so that a child never shares its outputs
+/// with its parent. The key and the buffer are stored in a `SBuf`, thus
+/// locked in memory, never copied when the generator is moved and
+/// zeroed-out when it is dropped.
+pub struct FastKeyErasureRng<A> {
+    sys: SysRng,
+    // key || buffered output
+    state: SBuf<A, u8>,
+    pos: uint,
+    pid: pid_t
+}
+
+impl<A: Allocator> FastKeyErasureRng<A> {
+    /// New instance seeded from `SysRng`.
+    pub fn new() -> IoResult<FastKeyErasureRng<A>> {
+        let state = match SBuf::try_new_zero(FKE_KEY_SIZE + FKE_BUF_SIZE) {
+            Ok(state) => state,
+            Err(err) => return Err(IoError {
+                kind: io::OtherIoError,
+                desc: "secure memory allocation failed",
+                detail: Some(format!("{}", err))
+            })
+        };
+        let mut rng = FastKeyErasureRng {
+            sys: SysRng::new(),
+            state: state,
+            pos: FKE_BUF_SIZE,
+            pid: 0
+        };
+        try!(rng.reseed());
+        Ok(rng)
+    }
+
+    /// Draw a new key from `SysRng` and discard buffered bytes.
+    pub fn reseed(&mut self) -> IoResult<()> {
+        {
+            let (key, buf) = self.state.split_at_mut(FKE_KEY_SIZE);
+            try!(self.sys.try_fill_bytes(key));
+            zero_memory(buf);
+        }
+        self.pos = FKE_BUF_SIZE;
+        self.pid = unsafe { unistd::getpid() };
+        Ok(())
+    }
+
+    // Expand the current key into the next key and a new buffer.
+    fn refill(&mut self) {
+        {
+            let (key, buf) = self.state.split_at_mut(FKE_KEY_SIZE);
+            let mut chacha: ChaChaRng = SeedableRng::from_seed(key[]);
+            chacha.fill_bytes(key);
+            chacha.fill_bytes(buf);
+        }
+        self.pos = 0;
+    }
+
+    /// Fill `dest` with random bytes. Only fails if reseeding after a
+    /// `fork()` failed.
+    pub fn try_fill_bytes(&mut self, dest: &mut [u8]) -> IoResult<()> {
+        if unsafe { unistd::getpid() } != self.pid {
+            try!(self.reseed());
+        }
+
+        let mut n = 0u;
+        while n < dest.len() {
+            if self.pos == FKE_BUF_SIZE {
+                self.refill();
+            }
+            let count = cmp::min(FKE_BUF_SIZE - self.pos, dest.len() - n);
+            let start = FKE_KEY_SIZE + self.pos;
+            let end = start + count;
+            copy_slice_memory(dest[mut n..n + count], self.state[start..end],
+                              count);
+            zero_memory(self.state[mut start..end]);
+            self.pos += count;
+            n += count;
+        }
+        Ok(())
+    }
+}
+
+impl<A: Allocator> Rng for FastKeyErasureRng<A> {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0u8, ..4];
+        self.fill_bytes(buf);
+        read_u32_le(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self.try_fill_bytes(dest) {
+            Ok(()) => (),
+            Err(err) => panic!("FastKeyErasureRng failed: {}", err)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serialize::hex::ToHex;
+    use std::path::BytesContainer;
+    use std::rand::{random, Rng, SeedableRng};
+
+    use sbuf::{GuardedHeapAllocator, SBuf};
+    use sstring::SString;
+    use utils;
+    use utils::{ChaChaRng, Choice, CtOption, CtOrd, CtSelect,
+                FastKeyErasureRng, SysRng, Zeroize, Zeroizing};
+    use utils::{Pkcs7Padding, Iso7816Padding, AnsiX923Padding,
+                Iso10126Padding, ZeroPadding};
+
+
+    #[test]
+    fn test_zero_memory() {
+        struct Test {
+            x: [u32, ..16],
+        };
+
+        let one = [1u32, ..16];
+        let zero = [0u32, ..16];
+        let mut s = Test {x: one};
+        assert!(s.x == one);
+        super::zero_memory(s.x[mut]);
+        assert!(s.x == zero);
+    }
+
+    #[test]
+    fn test_byte_eq() {
+        for _ in range(0u, 256) {
+            let a: u8 = random();
+            let b: u8 = random();
+            assert_eq!(super::byte_eq(a, b) == 1, a == b);
+        }
+    }
+
+    #[test]
+    fn test_bytes_eq() {
+        let a: [u8, ..64] = [0u8, ..64];
+        let b: [u8, ..64] = [0u8, ..64];
+        assert!(super::bytes_eq(a, b));
+
+        for _ in range(0u, 256) {
+            let va = Vec::from_fn(64, |_| random::<u8>());
+            let a = va.container_as_bytes();
+            let vb = Vec::from_fn(64, |_| random::<u8>());
+            let b = vb.container_as_bytes();
+            assert_eq!(super::bytes_eq(a, b), a == b);
+        }
+    }
+
+    #[test]
+    fn test_bytes_cswap() {
+        let mut a1: [i8, ..64] = [0i8, ..64];
+        let a2 = a1;
+        let mut b1: [i8, ..64] = [1i8, ..64];
+        let b2 = b1;
+
+        utils::bytes_cswap(0, a1, b1);
+        assert!(a1 == a2);
+        assert!(b1 == b2);
+
+        utils::bytes_cswap(1, a1, b1);
+        assert!(a1 == b2);
+        assert!(b1 == a2);
+    }
+
+    #[test]
+    fn test_copy_slice() {
+        let a: [i64, ..64] = [42, ..64];
+        let mut b: [i64, ..64] = [0, ..64];
+
+        assert!(a != b);
+        utils::copy_slice_memory(b[mut], a[], a.len());
+        assert!(a == b);
+    }
+
+    #[test]
+    fn test_pad16() {
+        assert!(utils::pad16(0).len() == 0);
+        assert!(utils::pad16(16).len() == 0);
+        assert!(utils::pad16(1).len() == 15);
+        assert!(utils::pad16(15).len() == 1);
+        assert!(utils::pad16(42).len() == 6);
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let mut a = 42u64;
+        a.zeroize();
+        assert_eq!(a, 0);
+
+        let mut b = [42i32, ..16];
+        b.zeroize();
+        assert!(b == [0i32, ..16]);
+
+        let mut c = Vec::from_elem(64, 42u8);
+        c.zeroize();
+        assert!(c.is_empty());
+
+        let mut d = String::from_str("secret");
+        d.zeroize();
+        assert!(d.is_empty());
+
+        let mut e = Some([42u8, ..32]);
+        e.zeroize();
+        assert!(e.is_none());
+
+        let mut f = Zeroizing::new([0u32, ..8]);
+        (*f)[3] = 42;
+        assert_eq!((*f)[3], 42);
+        f.zeroize();
+        assert!(*f == [0u32, ..8]);
+    }
+
+    #[test]
+    fn test_int_codecs() {
+        let b = [0x01u8, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        assert_eq!(utils::read_u16_le(b), 0x0201);
+        assert_eq!(utils::read_u16_be(b), 0x0102);
+        assert_eq!(utils::read_u32_le(b), 0x04030201);
+        assert_eq!(utils::read_u32_be(b), 0x01020304);
+        assert_eq!(utils::read_u64_le(b), 0x0807060504030201);
+        assert_eq!(utils::read_u64_be(b), 0x0102030405060708);
+
+        let mut c = [0u8, ..8];
+        utils::write_u64_be(c, 0x0102030405060708);
+        assert!(b == c);
+        utils::write_u32_le(c, 0x04030201);
+        assert!(b[..4] == c[..4]);
+
+        let mut v = 0xffffffffu32;
+        utils::u8to32_le(&mut v, b);
+        assert_eq!(v, 0x04030201);
+
+        let mut w = [0u32, ..2];
+        utils::read_u32_be_into(w, b);
+        assert!(w == [0x01020304, 0x05060708]);
+        let mut d = [0u8, ..8];
+        utils::write_u32_be_from(d, w);
+        assert!(b == d);
+
+        let mut x = [0u16, ..4];
+        utils::read_u16_le_into(x, b);
+        assert!(x == [0x0201, 0x0403, 0x0605, 0x0807]);
+        utils::write_u16_le_from(d, x);
+        assert!(b == d);
+    }
+
+    #[test]
+    fn test_pad_unpad() {
+        let schemes = [Pkcs7Padding, Iso7816Padding, AnsiX923Padding,
+                       Iso10126Padding, ZeroPadding];
+        for &scheme in schemes.iter() {
+            for len in range(0u, 40) {
+                let mut v = Vec::from_elem(len, 0xaau8);
+                v.push_all(utils::pad(scheme, len, 16)[]);
+                assert_eq!(v.len() % 16, 0);
+                assert_eq!(utils::unpad(scheme, v[], 16), Ok(len));
+            }
+        }
+        assert_eq!(utils::try_pad(Iso10126Padding, 5, 16).unwrap().len(), 11);
+
+        let block = [0xaau8, ..16];
+        assert!(utils::unpad(Pkcs7Padding, block, 16).is_err());
+        assert!(utils::unpad(Iso7816Padding, block, 16).is_err());
+        assert!(utils::unpad(AnsiX923Padding, block, 16).is_err());
+        assert!(utils::unpad(Iso10126Padding, block, 16).is_err());
+        assert!(utils::unpad(Pkcs7Padding, block[..15], 16).is_err());
+
+        let mut bad = [0xaau8, ..16];
+        bad[15] = 3;
+        bad[14] = 3;
+        assert!(utils::unpad(Pkcs7Padding, bad, 16).is_err());
+        assert_eq!(utils::unpad(Iso10126Padding, bad, 16), Ok(13));
+        bad[13] = 3;
+        assert_eq!(utils::unpad(Pkcs7Padding, bad, 16), Ok(13));
+        assert!(utils::unpad(Pkcs7Padding, [0u8, ..16], 16).is_err());
+        assert!(utils::unpad(Iso7816Padding, [0u8, ..16], 16).is_err());
+    }
+
+    #[test]
+    fn test_choice() {
+        let t = Choice::new(1);
+        let f = Choice::new(0);
+        assert!(t.to_bool());
+        assert!(!f.to_bool());
+        assert!((t & t).to_bool() && !(t & f).to_bool());
+        assert!((t | f).to_bool() && !(f | f).to_bool());
+        assert!((t ^ f).to_bool() && !(t ^ t).to_bool());
+        assert!((!f).to_bool() && !(!t).to_bool());
+        assert_eq!(Choice::new(3).unwrap_u8(), 1);
+
+        assert!(utils::bytes_ct_eq([1u8, 2, 3][], [1u8, 2, 3][]).to_bool());
+        assert!(!utils::bytes_ct_eq([1u8, 2, 3][], [1u8, 2, 4][]).to_bool());
+        assert!(!utils::bytes_ct_eq([1u8, 2][], [1u8, 2, 3][]).to_bool());
+
+        let a = CtOption::new(42u32, t);
+        assert!(a.is_some().to_bool() && !a.is_none().to_bool());
+        assert_eq!(a.clone().map(|x| x + 1).unwrap(), 43);
+        assert_eq!(a.to_option(), Some(42));
+        assert_eq!(CtOption::new(42u32, f).to_option(), None);
+        assert_eq!(CtOption::new(42u32, t).unwrap_or(7), 42);
+        assert_eq!(CtOption::new(42u32, f).unwrap_or(7), 7);
+
+        assert_eq!(format!("{}", t).as_slice(), "Choice(*)");
+        assert_eq!(format!("{}", CtOption::new(42u32, t)).as_slice(),
+                   "CtOption(*)");
+    }
+
+    #[test]
+    fn test_ct_ord() {
+        for _ in range(0u, 256) {
+            let a: u8 = random();
+            let b: u8 = random();
+            assert_eq!(utils::ct_lt(a, b).to_bool(), a < b);
+            assert_eq!(utils::ct_gt(a, b).to_bool(), a > b);
+            assert_eq!(utils::ct_le(a, b).to_bool(), a <= b);
+            assert_eq!(a.ct_ge(&b).to_bool(), a >= b);
+
+            let c: u32 = random();
+            let d: u32 = random();
+            assert_eq!(utils::ct_lt(c, d).to_bool(), c < d);
+            assert_eq!(utils::ct_lt(c, c).to_bool(), false);
+            assert_eq!(utils::ct_le(c, c).to_bool(), true);
+
+            let e: u64 = random();
+            let f: u64 = random();
+            assert_eq!(utils::ct_gt(e, f).to_bool(), e > f);
+        }
+        assert!(utils::ct_lt(0u64, !0u64).to_bool());
+        assert!(!utils::ct_lt(!0u64, 0u64).to_bool());
+    }
+
+    #[test]
+    fn test_bytes_cmp() {
+        assert_eq!(utils::bytes_cmp_be([1u8, 2, 3], [1u8, 2, 3]), 0);
+        assert_eq!(utils::bytes_cmp_be([1u8, 2, 3], [1u8, 3, 2]), -1);
+        assert_eq!(utils::bytes_cmp_be([2u8, 0, 0], [1u8, 255, 255]), 1);
+        assert_eq!(utils::bytes_cmp_le([2u8, 0, 0], [1u8, 255, 255]), -1);
+        assert_eq!(utils::bytes_cmp_le([0u8, 0, 2], [255u8, 255, 1]), 1);
+
+        // Lexicographic order of byte strings of different lengths.
+        assert_eq!(utils::bytes_cmp_be([1u8, 2], [1u8, 2, 0]), -1);
+        assert_eq!(utils::bytes_cmp_be([1u8, 2, 0], [1u8, 2]), 1);
+        assert_eq!(utils::bytes_cmp_be([1u8, 3], [1u8, 2, 0]), 1);
+        assert_eq!(utils::bytes_cmp_be([], [0u8]), -1);
+        assert_eq!(utils::bytes_cmp_be([], []), 0);
+
+        for _ in range(0u, 256) {
+            let a: [u8, ..2] = [random(), random()];
+            let b: [u8, ..2] = [random(), random()];
+            let x = (a[0] as u16 << 8) | a[1] as u16;
+            let y = (b[0] as u16 << 8) | b[1] as u16;
+            let expected = if x < y { -1 } else if x > y { 1 } else { 0 };
+            assert_eq!(utils::bytes_cmp_be(a, b), expected);
+        }
+    }
+
+    #[test]
+    fn test_ct_select() {
+        let t = Choice::new(1);
+        let f = Choice::new(0);
+
+        assert_eq!(utils::ct_select(t, &1u8, &2u8), 1);
+        assert_eq!(utils::ct_select(f, &1u8, &2u8), 2);
+        assert_eq!(utils::ct_select(t, &-1i64, &2i64), -1);
+        assert_eq!(utils::ct_select(f, &-1i64, &2i64), 2);
+
+        let mut a = 0xdeadbeefu32;
+        utils::ct_assign(f, &mut a, &42);
+        assert_eq!(a, 0xdeadbeef);
+        utils::ct_assign(t, &mut a, &42);
+        assert_eq!(a, 42);
+
+        let mut b = 1u64;
+        let mut c = 2u64;
+        utils::ct_swap(f, &mut b, &mut c);
+        assert!(b == 1 && c == 2);
+        utils::ct_swap(t, &mut b, &mut c);
+        assert!(b == 2 && c == 1);
+        b.ct_swap(t, &mut c);
+        assert!(b == 1 && c == 2);
+
+        let mut x = [0u16, ..16];
+        let mut y = [1u16, ..16];
+        let mut z = [0u16, ..16];
+        utils::ct_select_slice(t, z, y, x);
+        assert!(z == y);
+        utils::ct_assign_slice(t, z, x);
+        assert!(z == x);
+        utils::ct_swap_slice(t, x, y);
+        assert!(x == [1u16, ..16] && y == [0u16, ..16]);
+        utils::ct_swap_slice(f, x, y);
+        assert!(x == [1u16, ..16] && y == [0u16, ..16]);
+    }
+
+    #[test]
+    fn test_ct_lookup() {
+        let mut table = [[0i64, ..16], ..8];
+        for i in range(0u, 8) {
+            for j in range(0u, 16) {
+                table[i][j] = (i * 16 + j) as i64;
+            }
+        }
+
+        for i in range(0u, 8) {
+            let mut out = [-1i64, ..16];
+            utils::ct_lookup(table, i, &mut out);
+            assert!(out == table[i]);
+        }
+
+        let mut out = [-1i64, ..16];
+        utils::ct_lookup(table, 8, &mut out);
+        assert!(out == [-1i64, ..16]);
+    }
+
+    #[test]
+    fn test_hex() {
+        for len in range(0u, 64) {
+            let v = Vec::from_fn(len, |_| random::<u8>());
+            let h: SString<GuardedHeapAllocator> = utils::to_hex(v[]);
+            assert_eq!(h.as_str(), v[].to_hex().as_slice());
+            let b: SBuf<GuardedHeapAllocator, u8> =
+                utils::from_hex(h.as_str()).unwrap();
+            assert!(b[] == v[]);
+        }
+
+        let b: SBuf<GuardedHeapAllocator, u8> =
+            utils::from_hex("0123456789abcdefABCDEF").unwrap();
+        assert!(b[] == [0x01u8, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef,
+                        0xab, 0xcd, 0xef][]);
+
+        for s in ["0", "0g", "g0", "/0", ":0", "@0", "`0", "G0", "0 "].iter() {
+            let r: Result<SBuf<GuardedHeapAllocator, u8>, ()> =
+                utils::from_hex(*s);
+            assert!(r.is_err());
+        }
+
+        let mut d = [0x42u8, ..2];
+        assert!(utils::hex_decode(b"41zz", d).is_err());
+        assert!(d == [0u8, ..2]);
+    }
+
+    #[test]
+    fn test_chacha_rng() {
+        // Keystream of ChaCha20 with a null key and a null nonce.
+        let mut rng: ChaChaRng = SeedableRng::from_seed([0u8, ..32][]);
+        let expected = [0xade0b876u32, 0x903df1a0, 0xe56a5d40, 0x28bd8653];
+        for &e in expected.iter() {
+            assert_eq!(rng.next_u32(), e);
+        }
+
+        let seed = [42u8, ..32];
+        let mut a: ChaChaRng = SeedableRng::from_seed(seed[]);
+        let mut b: ChaChaRng = SeedableRng::from_seed(seed[]);
+        let va = Vec::from_fn(100, |_| a.next_u64());
+        let vb = Vec::from_fn(100, |_| b.next_u64());
+        assert!(va == vb);
+
+        b.reseed([43u8, ..32][]);
+        assert!(a.next_u64() != b.next_u64());
+        a.reseed(seed[]);
+        assert_eq!(a.next_u64(), va[0]);
+    }
+
+    #[test]
+    fn test_sys_rng() {
+        let mut rng = SysRng::new();
+        let mut a = [0u8, ..64];
+        let mut b = [0u8, ..64];
+        assert!(rng.try_fill_bytes(a).is_ok());
+        assert!(rng.try_fill_bytes(b).is_ok());
+        assert!(a[] != b[]);
+    }
+
+    #[test]
+    fn test_fast_key_erasure_rng() {
+        let mut rng: FastKeyErasureRng<GuardedHeapAllocator> =
+            FastKeyErasureRng::new().unwrap();
+        let mut a = [0u8, ..1000];
+        let mut b = [0u8, ..1000];
+        assert!(rng.try_fill_bytes(a).is_ok());
+        assert!(rng.try_fill_bytes(b).is_ok());
+        assert!(a[] != b[]);
+        assert!(rng.state[32..32 + rng.pos].iter().all(|&x| x == 0));
+
+        // Simulate a fork, a new key must be drawn.
+        let key = rng.state[..32].to_vec();
+        rng.pid = 0;
+        assert!(rng.try_fill_bytes(a).is_ok());
+        assert!(rng.state[..32] != key[]);
+    }
+}
@@ -0,0 +1,223 @@
+//! SHA-256 and HMAC-SHA-256
+//!
+//! Minimal implementation used by the DRBG, the hash states are
+//! zeroed-out when dropped.
+use std::cmp;
+
+use utils;
+
+
+static K: [u32, ..64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1,
+    0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3,
+    0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786,
+    0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147,
+    0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+    0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+    0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a,
+    0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+    0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+];
+
+static H0: [u32, ..8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+    0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+];
+
+/// Length in bytes of a digest.
+pub static DIGEST_LEN: uint = 32;
+
+/// Length in bytes of a block.
+pub static BLOCK_LEN: uint = 64;
+
+
+fn rotr(x: u32, n: uint) -> u32 {
+    (x >> n) | (x << (32 - n))
+}
+
+/// SHA-256 hash state.
+pub struct Sha256 {
+    h: [u32, ..8],
+    buf: [u8, ..BLOCK_LEN],
+    buf_len: uint,
+    len: u64
+}
+
+impl Sha256 {
+    /// New hash state.
+    pub fn new() -> Sha256 {
+        Sha256 {
+            h: H0,
+            buf: [0, ..BLOCK_LEN],
+            buf_len: 0,
+            len: 0
+        }
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32, ..64];
+        utils::read_u32_be_into(w[mut ..16], self.buf);
+        for i in range(16u, 64) {
+            let s0 = rotr(w[i - 15], 7) ^ rotr(w[i - 15], 18) ^ (w[i - 15] >> 3);
+            let s1 = rotr(w[i - 2], 17) ^ rotr(w[i - 2], 19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16] + s0 + w[i - 7] + s1;
+        }
+
+        let mut s = self.h;
+        for i in range(0u, 64) {
+            let s1 = rotr(s[4], 6) ^ rotr(s[4], 11) ^ rotr(s[4], 25);
+            let ch = (s[4] & s[5]) ^ (!s[4] & s[6]);
+            let t1 = s[7] + s1 + ch + K[i] + w[i];
+            let s0 = rotr(s[0], 2) ^ rotr(s[0], 13) ^ rotr(s[0], 22);
+            let maj = (s[0] & s[1]) ^ (s[0] & s[2]) ^ (s[1] & s[2]);
+            let t2 = s0 + maj;
+            s[7] = s[6];
+            s[6] = s[5];
+            s[5] = s[4];
+            s[4] = s[3] + t1;
+            s[3] = s[2];
+            s[2] = s[1];
+            s[1] = s[0];
+            s[0] = t1 + t2;
+        }
+        for i in range(0u, 8) {
+            self.h[i] += s[i];
+        }
+
+        utils::zero_memory(w[mut]);
+        utils::zero_memory(s[mut]);
+    }
+
+    /// Hash `data`.
+    pub fn input(&mut self, data: &[u8]) {
+        self.len += data.len() as u64;
+        let mut data = data;
+        while data.len() > 0 {
+            let n = cmp::min(BLOCK_LEN - self.buf_len, data.len());
+            utils::copy_slice_memory(self.buf[mut self.buf_len..], data, n);
+            self.buf_len += n;
+            data = data[n..];
+            if self.buf_len == BLOCK_LEN {
+                self.compress();
+                self.buf_len = 0;
+            }
+        }
+    }
+
+    /// Write the digest to the first `DIGEST_LEN` bytes of `out`, the
+    /// state must not be used anymore.
+    pub fn result(&mut self, out: &mut [u8]) {
+        let bits = self.len * 8;
+        let mut pad = [0u8, ..BLOCK_LEN + 8];
+        pad[0] = 0x80;
+        let n = if self.buf_len < 56 {
+            56 - self.buf_len
+        } else {
+            120 - self.buf_len
+        };
+        utils::write_u64_be(pad[mut n..], bits);
+        self.input(pad[..n + 8]);
+        utils::write_u32_be_from(out[mut ..DIGEST_LEN], self.h);
+    }
+}
+
+impl Drop for Sha256 {
+    fn drop(&mut self) {
+        utils::zero_memory(self.h[mut]);
+        utils::zero_memory(self.buf[mut]);
+    }
+}
+
+
+/// HMAC-SHA-256 state.
+pub struct Hmac {
+    inner: Sha256,
+    outer: Sha256
+}
+
+impl Hmac {
+    /// New state keyed with `key`.
+    pub fn new(key: &[u8]) -> Hmac {
+        let mut pad = [0u8, ..BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            let mut h = Sha256::new();
+            h.input(key);
+            h.result(pad[mut]);
+        } else {
+            utils::copy_slice_memory(pad[mut], key, key.len());
+        }
+
+        let mut inner = Sha256::new();
+        let mut outer = Sha256::new();
+        for b in pad.iter_mut() {
+            *b ^= 0x36;
+        }
+        inner.input(pad);
+        for b in pad.iter_mut() {
+            *b ^= 0x36 ^ 0x5c;
+        }
+        outer.input(pad);
+        utils::zero_memory(pad[mut]);
+
+        Hmac {
+            inner: inner,
+            outer: outer
+        }
+    }
+
+    /// Authenticate `data`.
+    pub fn input(&mut self, data: &[u8]) {
+        self.inner.input(data)
+    }
+
+    /// Write the MAC to the first `DIGEST_LEN` bytes of `out`, the state
+    /// must not be used anymore.
+    pub fn result(&mut self, out: &mut [u8]) {
+        let mut h = [0u8, ..DIGEST_LEN];
+        self.inner.result(h);
+        self.outer.input(h);
+        self.outer.result(out);
+        utils::zero_memory(h[mut]);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use serialize::hex::ToHex;
+
+    use utils::sha256::{Hmac, Sha256};
+
+
+    #[test]
+    fn test_sha256() {
+        let mut out = [0u8, ..32];
+        let mut h = Sha256::new();
+        h.input(b"abc");
+        h.result(out);
+        assert_eq!(out.to_hex().as_slice(),
+                   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let mut h = Sha256::new();
+        for _ in range(0u, 10) {
+            h.input([b'a', ..100]);
+        }
+        h.result(out);
+        assert_eq!(out.to_hex().as_slice(),
+                   "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3");
+    }
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231, test case 2.
+        let mut out = [0u8, ..32];
+        let mut h = Hmac::new(b"Jefe");
+        h.input(b"what do ya want ");
+        h.input(b"for nothing?");
+        h.result(out);
+        assert_eq!(out.to_hex().as_slice(),
+                   "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+}